@@ -1,10 +1,18 @@
 use clap::{App, Arg, SubCommand};
 use latexbuild::*;
 use std::fs::{create_dir, write};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
 
 mod subcommands;
 
+const CONFIG_HELP: &str = "Path to the configuration file, defaults to \
+                           latexproject.json, latexproject.toml or \
+                           latexproject.yaml in the current directory";
+
+const PROFILE_HELP: &str = "Profile to build with, as defined in the profiles \
+                            of the configuration file";
+
 const ENTRY_STR: &'static str = "\\documentclass{article}
 
 \\begin{document}
@@ -12,52 +20,106 @@ const ENTRY_STR: &'static str = "\\documentclass{article}
 \\end{document}
 ";
 
-fn main() {
+fn main() -> ExitCode {
     let matches = App::new("latexbuild")
         .version("0.3.2")
         .author("Zehua Chen peterchen06242000@outlook.com")
         .about("A tool to build latex projects")
-        .args(&[Arg::with_name("config")
-            .short("c")
-            .long("config")
-            .default_value("./latexproject.json")
-            .help("Path to the configuration file")])
+        .args(&[
+            Arg::with_name("quiet")
+                .short("q")
+                .long("quiet")
+                .global(true)
+                .conflicts_with("verbose")
+                .help("Only print errors"),
+            Arg::with_name("verbose")
+                .short("v")
+                .long("verbose")
+                .global(true)
+                .multiple(true)
+                .help("Print the commands run, and their complete output when given twice"),
+            Arg::with_name("message-format")
+                .long("message-format")
+                .global(true)
+                .takes_value(true)
+                .possible_values(&["human", "short", "json"])
+                .default_value("human")
+                .help(
+                    "Print human readable messages, errors and warnings as file:line: message, \
+                     or one JSON object per event to stdout",
+                ),
+        ])
+        .args(&[
+            Arg::with_name("config")
+                .short("c")
+                .long("config")
+                .global(true)
+                .takes_value(true)
+                .help(CONFIG_HELP),
+            Arg::with_name("profile")
+                .short("p")
+                .long("profile")
+                .global(true)
+                .takes_value(true)
+                .help(PROFILE_HELP),
+        ])
+        .subcommand(
+            SubCommand::with_name("build")
+                .args(&[Arg::with_name("target")
+                    .help("Target to build, all the targets if not specified")
+                    .index(1)])
+                .about("Build if needed"),
+        )
         .subcommand(
             SubCommand::with_name("clean")
-                .args(&[Arg::with_name("config")
-                    .short("c")
-                    .long("config")
-                    .help("Path to the configuration file")
-                    .default_value("./latexproject.json")])
+                .args(&[Arg::with_name("target")
+                    .help("Target whose bin directory to clean, all the targets if not specified")
+                    .index(1)])
                 .about("Clean build directory"),
         )
         .subcommand(
-            SubCommand::with_name("new")
-                .args(&[Arg::with_name("name")
-                    .short("n")
-                    .long("name")
-                    .help("Name of the project")
-                    .required(true)
+            SubCommand::with_name("watch")
+                .args(&[Arg::with_name("target")
+                    .help("Target to rebuild, all the targets if not specified")
                     .index(1)])
+                .about("Rebuild whenever the project changes"),
+        )
+        .subcommand(
+            SubCommand::with_name("why")
+                .args(&[Arg::with_name("target")
+                    .help("Target to check, all the targets if not specified")
+                    .index(1)])
+                .about("Print why the project would be built, without building it"),
+        )
+        .subcommand(
+            SubCommand::with_name("new")
+                .args(&[
+                    Arg::with_name("name")
+                        .short("n")
+                        .long("name")
+                        .help("Name of the project")
+                        .required(true)
+                        .index(1),
+                    Arg::with_name("format")
+                        .short("f")
+                        .long("format")
+                        .help("Format of the configuration file")
+                        .possible_values(&["json", "toml", "yaml"])
+                        .default_value("json"),
+                ])
                 .about("Create a new project"),
         )
         .subcommand(
             SubCommand::with_name("generate")
-                .args(&[Arg::with_name("config")
-                    .short("c")
-                    .long("config")
-                    .help("Path to the configuration file")
-                    .default_value("./latexproject.json")])
+                .args(&[Arg::with_name("target")
+                    .help("Target to generate rules for, all the targets if not specified")
+                    .index(1)])
                 .about("Generate a makefile"),
         )
         .get_matches();
 
-    match matches.subcommand() {
-        ("clean", Some(m)) => subcommands::clean(m),
-        ("new", Some(m)) => subcommands::new(m),
-        ("generate", Some(m)) => subcommands::generate(m),
-        _ => {
-            subcommands::build(&matches);
-        }
+    return match matches.subcommand() {
+        (name, Some(m)) => subcommands::run(name, m),
+        _ => subcommands::run("build", &matches),
     };
 }
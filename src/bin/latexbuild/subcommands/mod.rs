@@ -1,22 +1,133 @@
 use super::*;
 use clap::ArgMatches;
+use std::process::ExitCode;
 
 mod new;
 pub use new::*;
 
-pub fn clean(matches: &ArgMatches) {
-    let mut logger = StdErrLogger::new();
-    let mut latexbuild = LatexBuild {
-        config_path: PathBuf::from(matches.value_of("config").unwrap()),
-        logger: &mut logger,
+/// A latex pass has failed, or the build could not finish
+pub const EXIT_BUILD_FAILED: u8 = 1;
+/// The config could not be loaded, or refers to a file that does not exist
+pub const EXIT_CONFIG_ERROR: u8 = 2;
+/// The entry file does not exist
+pub const EXIT_NO_ENTRY: u8 = 3;
+/// The program of the engine could not be found
+pub const EXIT_ENGINE_NOT_FOUND: u8 = 4;
+/// A latex pass has been killed after running for longer than the timeout
+pub const EXIT_TIMEOUT: u8 = 5;
+
+/// Log an error and return the exit code for it
+fn report<L: Logger>(logger: &mut L, error: Error) -> ExitCode {
+    logger.error(format!("{}", error));
+
+    return ExitCode::from(error_code(&error));
+}
+
+/// The exit code for an error
+fn error_code(error: &Error) -> u8 {
+    return match error {
+        Error::PathNotFound(_)
+        | Error::JsonParsing(_, _)
+        | Error::TomlParsing(_, _)
+        | Error::YamlParsing(_, _)
+        | Error::WrongConfigFormat(_)
+        | Error::InvalidConfig(_)
+        | Error::UnknownTarget(_)
+        | Error::UnknownProfile(_) => EXIT_CONFIG_ERROR,
+        Error::NoEntry(_) => EXIT_NO_ENTRY,
+        Error::EngineNotFound(_) => EXIT_ENGINE_NOT_FOUND,
+        Error::Timeout(_, _) => EXIT_TIMEOUT,
+        _ => EXIT_BUILD_FAILED,
+    };
+}
+
+/// The exit code for the outcome of a build
+fn outcome_code(outcome: BuildOutcome) -> u8 {
+    return match outcome {
+        BuildOutcome::UpToDate | BuildOutcome::Built => 0,
+        BuildOutcome::Failed => EXIT_BUILD_FAILED,
+        BuildOutcome::NoEntry => EXIT_NO_ENTRY,
+        BuildOutcome::EngineNotFound => EXIT_ENGINE_NOT_FOUND,
+        BuildOutcome::TimedOut => EXIT_TIMEOUT,
     };
+}
+
+/// The config passed with `--config`, or the first of the default configs
+/// that exists in the current directory
+fn config_path(matches: &ArgMatches) -> PathBuf {
+    if let Some(config) = matches.value_of("config") {
+        return PathBuf::from(config);
+    }
 
-    match latexbuild.clean() {
-        Err(error) => {
-            logger.error(format!("{}", error));
+    for name in &DEFAULT_CONFIG_NAMES {
+        let path = Path::new(".").join(name);
+
+        if path.exists() {
+            return path;
         }
-        _ => {}
     }
+
+    return Path::new(".").join(DEFAULT_CONFIG_NAMES[0]);
+}
+
+/// Run a subcommand with the logger selected by `--message-format`, with the
+/// verbosity set by `--quiet` and `--verbose`
+///
+/// # Arguments
+///
+/// - `name`: the name of the subcommand, `build` if empty
+/// - `matches`: the matches of the subcommand
+pub fn run(name: &str, matches: &ArgMatches) -> ExitCode {
+    let verbosity = if matches.is_present("quiet") {
+        Verbosity::Quiet
+    } else {
+        match matches.occurrences_of("verbose") {
+            0 => Verbosity::Normal,
+            1 => Verbosity::Verbose,
+            _ => Verbosity::VeryVerbose,
+        }
+    };
+
+    return match matches.value_of("message-format") {
+        Some("json") => run_with(name, matches, &mut JsonLogger::with_verbosity(verbosity)),
+        Some("short") => {
+            // the files of the diagnostics are made relative to the directory
+            // of the config, like the paths in the config
+            let mut root = config_path(matches);
+            root.pop();
+
+            let mut logger =
+                StdErrLogger::with_diagnostic_format(verbosity, DiagnosticFormat::Short(root));
+
+            run_with(name, matches, &mut logger)
+        }
+        _ => run_with(name, matches, &mut StdErrLogger::with_verbosity(verbosity)),
+    };
+}
+
+fn run_with<L: Logger>(name: &str, matches: &ArgMatches, logger: &mut L) -> ExitCode {
+    return match name {
+        "clean" => clean(matches, logger),
+        "watch" => watch(matches, logger),
+        "why" => why(matches, logger),
+        "new" => new(matches, logger),
+        "generate" => generate(matches, logger),
+        _ => build(matches, logger),
+    };
+}
+
+pub fn clean<L: Logger>(matches: &ArgMatches, logger: &mut L) -> ExitCode {
+    let mut latexbuild = LatexBuild {
+        config_path: config_path(matches),
+        target: matches.value_of("target").map(String::from),
+        profile: matches.value_of("profile").map(String::from),
+        logger,
+    };
+
+    return match latexbuild.clean() {
+        Ok(_) => ExitCode::SUCCESS,
+        Err(error) => report(logger, error),
+    };
 }
 
 // pub fn generate(matches: &ArgMatches) {
@@ -29,32 +140,134 @@ pub fn clean(matches: &ArgMatches) {
 //     let project = latexbuild.load_project();
 // }
 
-pub fn build(matches: &ArgMatches) {
-    let mut logger = StdErrLogger::new();
+pub fn build<L: Logger>(matches: &ArgMatches, logger: &mut L) -> ExitCode {
+    let mut latexbuild = LatexBuild {
+        config_path: config_path(matches),
+        target: matches.value_of("target").map(String::from),
+        profile: matches.value_of("profile").map(String::from),
+        logger,
+    };
+
+    return match latexbuild.build() {
+        Ok(outcome) => ExitCode::from(outcome_code(outcome)),
+        Err(error) => report(logger, error),
+    };
+}
+
+pub fn watch<L: Logger>(matches: &ArgMatches, logger: &mut L) -> ExitCode {
+    let mut latexbuild = LatexBuild {
+        config_path: config_path(matches),
+        target: matches.value_of("target").map(String::from),
+        profile: matches.value_of("profile").map(String::from),
+        logger,
+    };
+
+    return match latexbuild.watch() {
+        Ok(_) => ExitCode::SUCCESS,
+        Err(error) => report(logger, error),
+    };
+}
+
+pub fn why<L: Logger>(matches: &ArgMatches, logger: &mut L) -> ExitCode {
     let mut latexbuild = LatexBuild {
-        config_path: PathBuf::from(matches.value_of("config").unwrap()),
-        logger: &mut logger,
+        config_path: config_path(matches),
+        target: matches.value_of("target").map(String::from),
+        profile: matches.value_of("profile").map(String::from),
+        logger,
+    };
+
+    let reasons = match latexbuild.why() {
+        Ok(reasons) => reasons,
+        Err(error) => return report(logger, error),
     };
 
-    match latexbuild.build() {
-        Err(error) => {
-            logger.error(format!("{}", error));
+    for (name, reason) in reasons {
+        let prefix = match name {
+            Some(name) => format!("{}: ", name),
+            None => String::new(),
+        };
+
+        match reason {
+            Some(reason) => {
+                logger.message(format!("{}needs build: {}", prefix, reason));
+            }
+            None => {
+                logger.message(format!("{}up to date", prefix));
+            }
         }
-        _ => {}
     }
+
+    return ExitCode::SUCCESS;
 }
 
-pub fn generate(matches: &ArgMatches) {
-    let mut logger = StdErrLogger::new();
+pub fn generate<L: Logger>(matches: &ArgMatches, logger: &mut L) -> ExitCode {
     let mut latexbuild = LatexBuild {
-        config_path: PathBuf::from(matches.value_of("config").unwrap()),
-        logger: &mut logger,
+        config_path: config_path(matches),
+        target: matches.value_of("target").map(String::from),
+        profile: matches.value_of("profile").map(String::from),
+        logger,
+    };
+
+    return match latexbuild.generate_make() {
+        Ok(_) => ExitCode::SUCCESS,
+        Err(error) => report(logger, error),
     };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn outcomes_have_exit_codes() {
+        assert_eq!(outcome_code(BuildOutcome::UpToDate), 0);
+        assert_eq!(outcome_code(BuildOutcome::Built), 0);
+        assert_eq!(outcome_code(BuildOutcome::Failed), 1);
+        assert_eq!(outcome_code(BuildOutcome::NoEntry), 3);
+        assert_eq!(outcome_code(BuildOutcome::EngineNotFound), 4);
+        assert_eq!(outcome_code(BuildOutcome::TimedOut), 5);
+    }
+
+    #[test]
+    fn errors_have_exit_codes() {
+        assert_eq!(
+            error_code(&Error::EngineFailed(String::from("pdflatex"), Some(1))),
+            1
+        );
+        assert_eq!(error_code(&Error::TooManyPasses(5, Vec::new())), 1);
+        assert_eq!(error_code(&Error::InvalidConfig(String::from("define"))), 2);
+        assert_eq!(
+            error_code(&Error::PathNotFound(PathBuf::from("latexproject.json"))),
+            2
+        );
+        assert_eq!(error_code(&Error::UnknownTarget(String::from("slides"))), 2);
+        assert_eq!(error_code(&Error::NoEntry(PathBuf::from("index.tex"))), 3);
+        assert_eq!(
+            error_code(&Error::EngineNotFound(String::from("xelatex"))),
+            4
+        );
+        assert_eq!(
+            error_code(&Error::Timeout(
+                String::from("pdflatex"),
+                Duration::from_secs(60)
+            )),
+            5
+        );
+    }
+
+    #[test]
+    fn outcomes_have_the_exit_codes_of_their_errors() {
+        let errors = vec![
+            Error::EngineFailed(String::from("pdflatex"), Some(1)),
+            Error::NoEntry(PathBuf::from("index.tex")),
+            Error::EngineNotFound(String::from("xelatex")),
+            Error::Timeout(String::from("pdflatex"), Duration::from_secs(60)),
+        ];
 
-    match latexbuild.generate_make() {
-        Err(error) => {
-            logger.error(format!("{}", error));
+        for error in &errors {
+            let outcome = BuildOutcome::from_error(error).unwrap();
+            assert_eq!(outcome_code(outcome), error_code(error));
         }
-        _ => {}
     }
 }
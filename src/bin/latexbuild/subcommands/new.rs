@@ -1,23 +1,30 @@
 use super::*;
 
-pub fn new(matches: &ArgMatches) {
+pub fn new<L: Logger>(matches: &ArgMatches, logger: &mut L) -> ExitCode {
     let name = matches.value_of("name").unwrap();
+    let format = ConfigFormat::from_name(matches.value_of("format").unwrap()).unwrap();
     let project_root = PathBuf::from(name);
-    let mut logger = StdErrLogger::new();
 
     // Create project dir
     logger.message("creating project directory");
-    create_dir(&project_root).unwrap();
+    if let Err(error) = create_dir(&project_root) {
+        return report(logger, Error::IO("create", project_root, error));
+    }
 
     // Create config file
     logger.message("creating config file");
     let mut config_path = project_root.clone();
     config_path.push("latexproject");
-    config_path.set_extension("json");
+    config_path.set_extension(format.extension());
 
-    let config_str = json::stringify_pretty(Project::new(), 2);
+    let config_str = match Config::default().to_string(format) {
+        Ok(config_str) => config_str,
+        Err(error) => return report(logger, error),
+    };
 
-    write(&config_path, config_str.as_bytes()).unwrap();
+    if let Err(error) = write(&config_path, config_str.as_bytes()) {
+        return report(logger, Error::IO("write", config_path, error));
+    }
 
     // Create entry file
     logger.message("creating entry file");
@@ -25,7 +32,11 @@ pub fn new(matches: &ArgMatches) {
     entry_path.push("index");
     entry_path.set_extension("tex");
 
-    write(&entry_path, ENTRY_STR.as_bytes()).unwrap();
+    if let Err(error) = write(&entry_path, ENTRY_STR.as_bytes()) {
+        return report(logger, Error::IO("write", entry_path, error));
+    }
 
     logger.message("done");
+
+    return ExitCode::SUCCESS;
 }
@@ -0,0 +1,314 @@
+use super::Error;
+use super::{absolute, BuildOutcome, Logger, Project, StepOutcome, Verbosity};
+use serde::{Deserialize, Serialize};
+use std::env::join_paths;
+use std::fs::{metadata, read};
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{Instant, SystemTime};
+
+/// The program used to process the bibliography
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Bibliography {
+    Bibtex,
+    Biber,
+}
+
+impl Bibliography {
+    pub fn name(&self) -> &'static str {
+        return match self {
+            Bibliography::Bibtex => "bibtex",
+            Bibliography::Biber => "biber",
+        };
+    }
+}
+
+/// Read the lines of the aux files
+fn read_aux_lines(project: &Project) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+
+    for aux in project.aux_files() {
+        if let Ok(content) = read(aux) {
+            lines.extend(String::from_utf8_lossy(&content).lines().map(String::from));
+        }
+    }
+
+    return lines;
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    return metadata(path).and_then(|meta| meta.modified()).ok();
+}
+
+impl Project {
+    /// The bbl file written by bibtex or biber
+    pub fn bbl(&self) -> PathBuf {
+        return self.aux().with_extension("bbl");
+    }
+
+    /// The bcf file written by biblatex for biber
+    pub fn bcf(&self) -> PathBuf {
+        return self.aux().with_extension("bcf");
+    }
+
+    /// The directory bibliography databases and styles are looked up in
+    fn bibliography_root(&self) -> PathBuf {
+        return match self.entry().parent() {
+            Some(parent) => absolute(parent),
+            None => absolute(Path::new(".")),
+        };
+    }
+
+    /// Determine the program to process the bibliography with from the files
+    /// written by the last latex pass
+    ///
+    /// # Returns
+    ///
+    /// - `None` if the document has no bibliography
+    /// - the program set in the project specification if there is one
+    /// - `bibtex` if the aux file contains `\bibdata`, `biber` if biblatex
+    ///   has written a bcf file or `\abx@aux` entries
+    pub fn detect_bibliography(&self) -> Option<Bibliography> {
+        let lines = read_aux_lines(self);
+
+        let has_bibdata = lines.iter().any(|line| line.starts_with("\\bibdata{"));
+        let has_biblatex = lines.iter().any(|line| line.starts_with("\\abx@aux"));
+        let has_bcf = self.bcf().exists();
+
+        if !has_bibdata && !has_biblatex && !has_bcf {
+            return None;
+        }
+
+        if let Some(bibliography) = self.bibliography() {
+            return Some(bibliography);
+        }
+
+        if has_bibdata {
+            return Some(Bibliography::Bibtex);
+        }
+
+        return Some(Bibliography::Biber);
+    }
+
+    /// The bib files used by the document, as listed in `\bibdata` of the aux
+    /// file or in the datasources of the bcf file. Only files that exist are
+    /// returned
+    pub fn bib_files(&self) -> Vec<PathBuf> {
+        let root = self.bibliography_root();
+        let mut names: Vec<String> = Vec::new();
+
+        let lines = read_aux_lines(self);
+
+        for line in &lines {
+            if let Some(bibdata) = line.strip_prefix("\\bibdata{") {
+                for name in bibdata.trim_end_matches('}').split(',') {
+                    names.push(String::from(name.trim()));
+                }
+            }
+        }
+
+        if let Ok(bcf) = read(self.bcf()) {
+            let bcf = String::from_utf8_lossy(&bcf);
+
+            for datasource in bcf.split("<bcf:datasource").skip(1) {
+                let start = match datasource.find('>') {
+                    Some(start) => start + 1,
+                    None => continue,
+                };
+
+                match datasource[start..].find("</bcf:datasource>") {
+                    Some(end) => names.push(String::from(datasource[start..start + end].trim())),
+                    None => {}
+                }
+            }
+        }
+
+        let mut files: Vec<PathBuf> = Vec::new();
+
+        for name in names {
+            let mut file = root.join(&name);
+
+            if file.extension().is_none() {
+                file.set_extension("bib");
+            }
+
+            if file.exists() && !files.contains(&file) {
+                files.push(file);
+            }
+        }
+
+        return files;
+    }
+
+    /// The data bibtex or biber reads from the output directory, used to
+    /// determine if the citations have changed
+    fn citation_data(&self) -> Vec<u8> {
+        let lines = read_aux_lines(self);
+
+        let mut data: Vec<u8> = Vec::new();
+
+        for line in lines {
+            if line.starts_with("\\citation{")
+                || line.starts_with("\\bibdata{")
+                || line.starts_with("\\bibstyle{")
+                || line.starts_with("\\abx@aux")
+            {
+                data.extend_from_slice(line.as_bytes());
+                data.push(b'\n');
+            }
+        }
+
+        if let Ok(bcf) = read(self.bcf()) {
+            data.extend(bcf);
+        }
+
+        return data;
+    }
+
+    /// Run bibtex or biber inside the bin directory
+    ///
+    /// # Arguments
+    ///
+    /// - `bibliography`: the program to run
+    /// - `logger`: the logger
+    ///
+    /// # Returns
+    ///
+    /// `true` if the program exited successfully
+    pub fn build_bibliography<L: Logger>(
+        &self,
+        bibliography: Bibliography,
+        logger: &mut L,
+    ) -> Result<bool, Error> {
+        let job = match self.aux().file_stem() {
+            Some(job) => job,
+            None => return Err(Error::Encoding),
+        };
+
+        // bibtex and biber run inside the bin directory, so the directory of
+        // the entry has to be added to their search paths. The trailing
+        // separator keeps the default search paths
+        let search_path = match join_paths(&[self.bibliography_root(), PathBuf::new()]) {
+            Ok(search_path) => search_path,
+            Err(_) => return Err(Error::Encoding),
+        };
+
+        logger.run_command(bibliography.name(), [job]);
+
+        let started = Instant::now();
+
+        let command_output = match Command::new(bibliography.name())
+            .arg(job)
+            .current_dir(self.bin())
+            .env("BIBINPUTS", &search_path)
+            .env("BSTINPUTS", &search_path)
+            .output()
+        {
+            Ok(output) => output,
+            Err(error) if error.kind() == ErrorKind::NotFound => {
+                return Err(Error::EngineNotFound(String::from(bibliography.name())));
+            }
+            Err(error) => return Err(Error::IO("run", PathBuf::from(bibliography.name()), error)),
+        };
+
+        logger.command_finished(
+            bibliography.name(),
+            command_output.status.code(),
+            started.elapsed(),
+        );
+
+        if logger.verbosity() >= Verbosity::VeryVerbose {
+            logger.command_output(String::from_utf8_lossy(&command_output.stdout));
+        }
+
+        return Ok(command_output.status.success());
+    }
+}
+
+/// An object that determines if bibtex or biber needs to run after a latex
+/// pass, and runs it
+///
+/// # Discussion
+///
+/// - `build()` should be called after every latex pass
+pub struct BibliographyBuilder<'a> {
+    pub project: &'a Project,
+    old_citations: Vec<u8>,
+}
+
+impl<'a> BibliographyBuilder<'a> {
+    /// Create a bibliography builder using a project, the citations are
+    /// those of the last build, before any latex pass is run
+    pub fn new(project: &'a Project) -> BibliographyBuilder<'a> {
+        BibliographyBuilder {
+            project,
+            old_citations: project.citation_data(),
+        }
+    }
+
+    /// Run bibtex or biber if the document has a bibliography and
+    ///
+    /// - there is no bbl file yet
+    /// - a bib file is newer than the bbl file
+    /// - the citations have changed since the last run
+    ///
+    /// # Returns
+    ///
+    /// `StepOutcome::Changed` if the bbl file has changed, in which case
+    /// latex needs another pass, and `StepOutcome::Failed` if the program is
+    /// not installed or has failed
+    pub fn build<L: Logger>(&mut self, logger: &mut L) -> Result<StepOutcome, Error> {
+        let bibliography = match self.project.detect_bibliography() {
+            Some(bibliography) => bibliography,
+            None => return Ok(StepOutcome::Unchanged),
+        };
+
+        let bbl = self.project.bbl();
+        let citations = self.project.citation_data();
+
+        let needs_build = match modified(&bbl) {
+            Some(bbl_modified) => {
+                citations != self.old_citations
+                    || self
+                        .project
+                        .bib_files()
+                        .iter()
+                        .any(|file| match modified(file) {
+                            Some(file_modified) => bbl_modified < file_modified,
+                            None => false,
+                        })
+            }
+            None => true,
+        };
+
+        self.old_citations = citations;
+
+        if !needs_build {
+            return Ok(StepOutcome::Unchanged);
+        }
+
+        let old_bbl = read(&bbl).ok();
+
+        logger.message(format!(
+            "building bibliography with {}",
+            bibliography.name()
+        ));
+
+        match self.project.build_bibliography(bibliography, logger) {
+            Ok(true) => {}
+            Ok(false) => {
+                logger.error(format!("{} stopped due to error", bibliography.name()));
+                return Ok(StepOutcome::Failed(BuildOutcome::Failed));
+            }
+            Err(error) => return StepOutcome::from_error(error, logger),
+        }
+
+        if read(&bbl).ok() != old_bbl {
+            return Ok(StepOutcome::Changed);
+        }
+
+        return Ok(StepOutcome::Unchanged);
+    }
+}
@@ -1,7 +1,197 @@
 use super::Error;
-use super::{Logger, Project};
-use std::fs::create_dir;
-use std::process::Command;
+use super::{parse_log, relative_to_root, Diagnostic, Engine, EngineOutput, Logger, Project};
+use super::{ShellEscape, Verbosity};
+use std::ffi::{OsStr, OsString};
+use std::fs::{create_dir_all, read};
+use std::io::{BufRead, BufReader, ErrorKind, Read};
+use std::path::{Component, Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{channel, RecvTimeoutError, Sender};
+use std::thread::spawn;
+use std::time::Instant;
+use wait_timeout::ChildExt;
+
+/// The characters of a path that keep their meaning in tex code even inside
+/// the quotes of `\input{"..."}`
+const TEX_SPECIAL_CHARS: [char; 9] = ['\\', '{', '}', '%', '#', '~', '^', '$', '"'];
+
+/// The path of a file as written in `\input{"..."}`: relative to the current
+/// directory, which latex runs in, and with `/` separators, which tex reads
+/// on every platform
+fn input_path(path: &Path) -> Result<String, Error> {
+    let relative = relative_to_root(path, Path::new("."));
+    let mut input = String::new();
+
+    for component in relative.components() {
+        let name = match component {
+            Component::Prefix(prefix) => prefix.as_os_str(),
+            Component::RootDir => {
+                input.push('/');
+                continue;
+            }
+            Component::CurDir => continue,
+            Component::ParentDir => OsStr::new(".."),
+            Component::Normal(name) => name,
+        };
+
+        if !input.is_empty() && !input.ends_with('/') {
+            input.push('/');
+        }
+
+        match name.to_str() {
+            Some(name) => input.push_str(name),
+            None => return Err(Error::Encoding),
+        }
+    }
+
+    if input.contains(TEX_SPECIAL_CHARS) {
+        return Err(Error::InvalidConfig(format!(
+            "{} cannot be input after the defines, rename it without {}",
+            path.display(),
+            TEX_SPECIAL_CHARS.iter().collect::<String>()
+        )));
+    }
+
+    return Ok(input);
+}
+
+/// The outcome of a single latex pass
+pub struct BuildOutput {
+    /// `true` if latex exited successfully
+    pub success: bool,
+    /// The exit code of latex, `None` if it was killed by a signal
+    pub code: Option<i32>,
+    /// The diagnostics parsed from the log file written by the pass
+    pub diagnostics: Vec<Diagnostic>,
+    /// The message of the first warning in the log asking for another pass
+    pub rerun: Option<String>,
+}
+
+/// The outcome of building one or more targets, ordered from the best to the
+/// worst
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum BuildOutcome {
+    /// No latex pass was needed
+    UpToDate,
+    /// The latex passes have succeeded
+    Built,
+    /// latex, or the program converting its output to pdf, has failed, or
+    /// the passes have not stabilised
+    Failed,
+    /// The entry file does not exist
+    NoEntry,
+    /// The program of the engine could not be found
+    EngineNotFound,
+    /// A latex pass has been killed after running for longer than the
+    /// timeout
+    TimedOut,
+}
+
+impl BuildOutcome {
+    pub fn name(&self) -> &'static str {
+        return match self {
+            BuildOutcome::UpToDate => "up-to-date",
+            BuildOutcome::Built => "built",
+            BuildOutcome::Failed => "failed",
+            BuildOutcome::NoEntry => "no-entry",
+            BuildOutcome::EngineNotFound => "engine-not-found",
+            BuildOutcome::TimedOut => "timed-out",
+        };
+    }
+
+    /// The outcome of a target whose build has stopped with an error, `None`
+    /// if the error is not specific to the target and stops the whole build
+    pub fn from_error(error: &Error) -> Option<BuildOutcome> {
+        return match error {
+            Error::EngineFailed(_, _) => Some(BuildOutcome::Failed),
+            Error::NoEntry(_) => Some(BuildOutcome::NoEntry),
+            Error::EngineNotFound(_) => Some(BuildOutcome::EngineNotFound),
+            Error::Timeout(_, _) => Some(BuildOutcome::TimedOut),
+            _ => None,
+        };
+    }
+}
+
+/// The outcome of a program run after a latex pass, such as bibtex or
+/// makeindex
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StepOutcome {
+    /// The program was not needed, or has not changed its output
+    Unchanged,
+    /// The output of the program has changed, latex needs another pass
+    Changed,
+    /// The program could not be run or has failed, with the outcome of the
+    /// target
+    Failed(BuildOutcome),
+}
+
+impl StepOutcome {
+    /// Log the error of a program run after a latex pass and make it the
+    /// outcome of the step, errors that are not specific to the target are
+    /// returned
+    pub(crate) fn from_error<L: Logger>(
+        error: Error,
+        logger: &mut L,
+    ) -> Result<StepOutcome, Error> {
+        return match BuildOutcome::from_error(&error) {
+            Some(outcome) => {
+                logger.error(format!("{}", error));
+                Ok(StepOutcome::Failed(outcome))
+            }
+            None => Err(error),
+        };
+    }
+}
+
+/// A line written by a child process
+enum OutputLine {
+    Stdout(String),
+    Stderr(String),
+}
+
+/// Read a pipe of a child process line by line on another thread, sending
+/// the lines as they are written so that they can be logged while the child
+/// runs
+///
+/// # Arguments
+///
+/// - `pipe`: the pipe
+/// - `sender`: where the lines are sent
+/// - `to_line`: `OutputLine::Stdout` or `OutputLine::Stderr`
+fn read_lines_on_thread<R>(
+    pipe: Option<R>,
+    sender: Sender<OutputLine>,
+    to_line: fn(String) -> OutputLine,
+) where
+    R: Read + Send + 'static,
+{
+    let pipe = match pipe {
+        Some(pipe) => pipe,
+        None => return,
+    };
+
+    spawn(move || {
+        let mut reader = BufReader::new(pipe);
+        let mut line: Vec<u8> = Vec::new();
+
+        loop {
+            line.clear();
+
+            match reader.read_until(b'\n', &mut line) {
+                Ok(0) | Err(_) => return,
+                Ok(_) => {}
+            }
+
+            // tex writes the output in the encoding of the input files,
+            // which is not always utf-8
+            let line = String::from_utf8_lossy(&line).trim_end().to_string();
+
+            if sender.send(to_line(line)).is_err() {
+                return;
+            }
+        }
+    });
+}
 
 impl Project {
     /// Build a project
@@ -9,48 +199,316 @@ impl Project {
     /// # Arguments
     ///
     /// - `logger`: the logger
-    pub fn build<L: Logger>(&self, logger: &mut L) -> Result<bool, Error> {
+    pub fn build<L: Logger>(&self, logger: &mut L) -> Result<BuildOutput, Error> {
         if !self.bin().exists() {
             logger.message("creating bin directory");
 
-            match create_dir(self.bin()) {
-                Err(error) => return Err(Error::IO(error)),
+            match create_dir_all(self.bin()) {
+                Err(error) => return Err(Error::IO("create", PathBuf::from(self.bin()), error)),
                 _ => {}
             }
         }
 
+        let args = self.latex_args()?;
+
+        logger.run_command(self.latex(), &args);
+
+        let started = Instant::now();
+
+        // with stdin closed, latex stops instead of waiting for input on
+        // errors even if the interaction mode would ask for it
+        let mut child = match Command::new(self.latex())
+            .args(&args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+        {
+            Ok(child) => child,
+            Err(error) if error.kind() == ErrorKind::NotFound => {
+                return Err(Error::EngineNotFound(
+                    self.latex().to_string_lossy().into_owned(),
+                ));
+            }
+            Err(error) => return Err(Error::IO("run", PathBuf::from(self.latex()), error)),
+        };
+
+        // the output is shown as the project asks for it, otherwise its
+        // progress is shown, in full when very verbose
+        let engine_output = match (logger.verbosity(), self.engine_output()) {
+            (Verbosity::Quiet, _) => None,
+            (_, Some(engine_output)) => Some(engine_output),
+            (Verbosity::VeryVerbose, None) => Some(EngineOutput::Full),
+            (_, None) => Some(EngineOutput::Progress),
+        };
+
+        let show_errors = logger.verbosity() >= Verbosity::Verbose;
+
+        let (sender, receiver) = channel();
+        read_lines_on_thread(child.stdout.take(), sender.clone(), OutputLine::Stdout);
+        read_lines_on_thread(child.stderr.take(), sender, OutputLine::Stderr);
+
+        let deadline = self.timeout().map(|timeout| Instant::now() + timeout);
+        let mut has_timed_out = false;
+
+        // the channel is disconnected once both pipes are closed, which is
+        // usually when the child exits
+        loop {
+            let line = match deadline {
+                Some(deadline) => {
+                    receiver.recv_timeout(deadline.saturating_duration_since(Instant::now()))
+                }
+                None => receiver.recv().map_err(|_| RecvTimeoutError::Disconnected),
+            };
+
+            match line {
+                Ok(OutputLine::Stdout(line)) => {
+                    if engine_output.map(|output| output.shows(&line)) == Some(true) {
+                        logger.command_output(&line);
+                    }
+                }
+                Ok(OutputLine::Stderr(line)) => {
+                    if show_errors {
+                        logger.command_error_output(&line);
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => {
+                    has_timed_out = true;
+                    break;
+                }
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+        }
+
+        let status = match deadline {
+            Some(deadline) if !has_timed_out => {
+                match child.wait_timeout(deadline.saturating_duration_since(Instant::now())) {
+                    Ok(status) => status,
+                    Err(error) => {
+                        return Err(Error::IO("wait for", PathBuf::from(self.latex()), error));
+                    }
+                }
+            }
+            Some(_) => None,
+            None => match child.wait() {
+                Ok(status) => Some(status),
+                Err(error) => {
+                    return Err(Error::IO("wait for", PathBuf::from(self.latex()), error));
+                }
+            },
+        };
+
+        logger.command_finished(
+            self.latex(),
+            status.and_then(|status| status.code()),
+            started.elapsed(),
+        );
+
+        let status = match status {
+            Some(status) => status,
+            None => {
+                // the status is not needed, the process is being killed
+                // because it has not exited
+                let _ = child.kill();
+                let _ = child.wait();
+
+                return Err(Error::Timeout(
+                    self.latex().to_string_lossy().into_owned(),
+                    self.timeout().unwrap_or_default(),
+                ));
+            }
+        };
+
+        let diagnostics = self.diagnostics();
+        let rerun = diagnostics
+            .iter()
+            .find(|diagnostic| diagnostic.requests_rerun())
+            .map(|diagnostic| diagnostic.message.clone());
+
+        return Ok(BuildOutput {
+            success: status.success(),
+            code: status.code(),
+            diagnostics,
+            rerun,
+        });
+    }
+
+    /// The arguments passed to the engine
+    pub(crate) fn latex_args(&self) -> Result<Vec<String>, Error> {
         let bin = match self.bin().to_str() {
             Some(s) => s,
             None => return Err(Error::Encoding),
         };
 
-        let output_dir_arg = format!("-output-directory={}", bin);
+        let mut args: Vec<String> = Vec::new();
 
-        let entry = match self.entry().to_str() {
-            Some(s) => s,
-            None => return Err(Error::Encoding),
+        match self.engine() {
+            Engine::Tectonic => {
+                // tectonic takes neither a job name, which is why the job name
+                // of tectonic projects is the name of the entry, nor tex code,
+                // which is why it cannot have defines
+                args.push(String::from("--outdir"));
+                args.push(String::from(bin));
+                args.push(String::from("--keep-intermediates"));
+                args.push(String::from("--keep-logs"));
+
+                // tectonic never waits for input and always prints
+                // `file:line:` errors, and cannot restrict shell escape
+                if self.synctex() {
+                    args.push(String::from("--synctex"));
+                }
+
+                if self.shell_escape() == Some(ShellEscape::On) {
+                    args.push(String::from("-Z"));
+                    args.push(String::from("shell-escape"));
+                }
+
+                args.extend(self.args().iter().cloned());
+                args.push(self.entry_arg()?);
+            }
+            _ => {
+                // -recorder makes latex write the fls file, listing every
+                // file read
+                args.push(String::from("-recorder"));
+                args.push(format!("-output-directory={}", bin));
+
+                if let Some(jobname_arg) = self.jobname_arg() {
+                    args.push(jobname_arg);
+                }
+
+                args.push(format!("-interaction={}", self.interaction().name()));
+
+                if self.synctex() {
+                    args.push(String::from("-synctex=1"));
+                }
+
+                match self.shell_escape() {
+                    Some(ShellEscape::Off) => args.push(String::from("-no-shell-escape")),
+                    Some(ShellEscape::Restricted) => args.push(String::from("-shell-restricted")),
+                    Some(ShellEscape::On) => args.push(String::from("-shell-escape")),
+                    None => {}
+                }
+
+                if self.file_line_error() {
+                    args.push(String::from("-file-line-error"));
+                }
+
+                args.extend(self.args().iter().cloned());
+                args.push(self.entry_arg()?);
+            }
+        }
+
+        return Ok(args);
+    }
+
+    /// The dvi written by engines that do not write a pdf
+    pub fn dvi(&self) -> PathBuf {
+        return self.aux().with_extension("dvi");
+    }
+
+    /// The arguments passed to the program converting the dvi into the pdf
+    pub(crate) fn converter_args(&self) -> Vec<OsString> {
+        return vec![
+            OsString::from("-o"),
+            self.pdf().as_os_str().to_owned(),
+            self.dvi().into_os_string(),
+        ];
+    }
+
+    /// Convert the dvi written by the engine into the pdf, for engines that
+    /// do not write a pdf
+    ///
+    /// # Arguments
+    ///
+    /// - `logger`: the logger
+    ///
+    /// # Returns
+    ///
+    /// `true` if the conversion succeeded or was not needed
+    pub fn convert<L: Logger>(&self, logger: &mut L) -> Result<bool, Error> {
+        let converter = match self.engine().converter() {
+            Some(converter) => converter,
+            None => return Ok(true),
         };
 
-        logger.run_command(self.latex(), &[&output_dir_arg, entry]);
+        let args = self.converter_args();
 
-        let command_output = match Command::new(self.latex())
-            .args(&[&output_dir_arg, entry])
-            .output()
-        {
+        logger.run_command(converter, &args);
+
+        let started = Instant::now();
+
+        let command_output = match Command::new(converter).args(&args).output() {
             Ok(output) => output,
-            Err(error) => return Err(Error::IO(error)),
+            Err(error) if error.kind() == ErrorKind::NotFound => {
+                return Err(Error::EngineNotFound(String::from(converter)));
+            }
+            Err(error) => return Err(Error::IO("run", PathBuf::from(converter), error)),
         };
 
-        let command_output_str = match String::from_utf8(command_output.stdout) {
-            Ok(s) => s,
-            Err(_error) => return Err(Error::Encoding),
-        };
+        logger.command_finished(converter, command_output.status.code(), started.elapsed());
 
-        logger.command_output(&command_output_str);
+        if logger.verbosity() >= Verbosity::VeryVerbose {
+            logger.command_output(String::from_utf8_lossy(&command_output.stdout));
+        }
+
+        if logger.verbosity() >= Verbosity::Verbose && !command_output.stderr.is_empty() {
+            logger.command_error_output(String::from_utf8_lossy(&command_output.stderr));
+        }
 
         return Ok(command_output.status.success());
     }
 
+    /// The `-jobname` argument naming the output files, `None` if they are
+    /// named after the entry as latex does by default
+    pub(crate) fn jobname_arg(&self) -> Option<String> {
+        let entry_stem = self.entry().file_stem().map(|stem| stem.to_string_lossy());
+
+        // with defines, latex is not given the entry as a file name to take
+        // the job name from
+        if self.defines().is_empty() && entry_stem.as_deref() == Some(self.jobname()) {
+            return None;
+        }
+
+        return Some(format!("-jobname={}", self.jobname()));
+    }
+
+    /// The last argument passed to `latex`: the entry, or the `\def`s of the
+    /// defines followed by `\input` of the entry
+    ///
+    /// # Returns
+    ///
+    /// `Err(Error::InvalidConfig)` if there are defines and the path of the
+    /// entry has characters that tex code cannot quote
+    pub(crate) fn entry_arg(&self) -> Result<String, Error> {
+        let entry = match self.entry().to_str() {
+            Some(s) => s,
+            None => return Err(Error::Encoding),
+        };
+
+        if self.defines().is_empty() {
+            return Ok(String::from(entry));
+        }
+
+        let mut arg = String::new();
+
+        for (name, value) in self.defines() {
+            arg.push_str(&format!("\\def\\{}{{{}}}", name, value));
+        }
+
+        arg.push_str(&format!("\\input{{\"{}\"}}", input_path(self.entry())?));
+
+        return Ok(arg);
+    }
+
+    /// Parse the diagnostics out of the log file of the last build, no
+    /// diagnostics are returned if there is no log file
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        return match read(self.log()) {
+            Ok(log) => parse_log(&String::from_utf8_lossy(&log)),
+            Err(_) => Vec::new(),
+        };
+    }
+
     /// Determine if a project is buildable, it is recommended to call this
     /// method before calling the `build` method
     ///
@@ -60,9 +518,75 @@ impl Project {
     /// - `Err(ProjectBuildError)` if not
     pub fn can_build(&self) -> Result<(), Error> {
         if !self.entry().exists() {
-            return Err(Error::NoEntry);
+            return Err(Error::NoEntry(PathBuf::from(self.entry())));
         }
 
+        // an entry that cannot be input is reported before building
+        self.entry_arg()?;
+
         return Ok(());
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Config;
+
+    fn project(entry: &str, defines: &[(&str, &str)]) -> Project {
+        let mut config = Config {
+            entry: PathBuf::from(entry),
+            ..Config::default()
+        };
+
+        for (name, value) in defines {
+            config
+                .defines
+                .insert(String::from(*name), String::from(*value));
+        }
+
+        return Project::from_config(config, Path::new(".")).unwrap();
+    }
+
+    #[test]
+    fn entry_arg_without_defines_is_the_entry() {
+        assert_eq!(
+            project("./my thesis.tex", &[]).entry_arg().unwrap(),
+            "./my thesis.tex"
+        );
+    }
+
+    #[test]
+    fn entry_arg_inputs_the_quoted_entry_after_the_defines() {
+        let project = project(
+            "./chapters/my thesis.tex",
+            &[("final", "1"), ("title", "\\textbf{Draft}")],
+        );
+
+        assert_eq!(
+            project.entry_arg().unwrap(),
+            "\\def\\final{1}\\def\\title{\\textbf{Draft}}\\input{\"chapters/my thesis.tex\"}"
+        );
+    }
+
+    #[test]
+    fn entry_arg_rejects_tex_special_characters() {
+        for entry in &["100%.tex", "#1.tex", "~draft.tex", "a{b}.tex"] {
+            assert!(matches!(
+                project(entry, &[("final", "1")]).entry_arg(),
+                Err(Error::InvalidConfig(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn input_path_is_relative_to_the_current_directory() {
+        let entry = std::env::current_dir().unwrap().join("index.tex");
+
+        assert_eq!(input_path(&entry).unwrap(), "index.tex");
+        assert_eq!(
+            input_path(Path::new("../index.tex")).unwrap(),
+            "../index.tex"
+        );
+    }
+}
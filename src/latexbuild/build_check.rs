@@ -1,7 +1,69 @@
 use super::Error;
-use super::Project;
+use super::{hash_content, BuildState, Project, RebuildStrategy};
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::{self, Display, Formatter};
 use std::fs::{metadata, read};
-use std::path::PathBuf;
+use std::mem::replace;
+use std::path::{Path, PathBuf};
+
+/// The reason a build is needed
+#[derive(Clone, Debug, PartialEq)]
+pub enum RebuildReason {
+    /// The pdf does not exist
+    PdfMissing,
+    /// The last build has failed, the pdf it has left may be out of date
+    LastBuildFailed,
+    /// A source has changed since the pdf was built
+    SourceChanged(PathBuf),
+    /// The aux file, or the aux file of an `\include`, has changed during the
+    /// last pass
+    AuxChanged,
+    /// A generated file such as the `.toc` has changed during the last pass
+    GeneratedChanged(PathBuf),
+    /// There was no aux file before the last pass
+    AuxMissing,
+    /// The log of the last pass contains a warning asking for another pass,
+    /// with the message of the warning
+    RerunRequested(String),
+    /// bibtex or biber has changed the bbl file
+    BibliographyChanged,
+    /// makeindex, xindy or makeglossaries has changed a generated file
+    IndexChanged,
+}
+
+impl RebuildReason {
+    pub fn name(&self) -> &'static str {
+        return match self {
+            RebuildReason::PdfMissing => "pdf-missing",
+            RebuildReason::LastBuildFailed => "last-build-failed",
+            RebuildReason::SourceChanged(_) => "source-changed",
+            RebuildReason::AuxChanged => "aux-changed",
+            RebuildReason::GeneratedChanged(_) => "generated-changed",
+            RebuildReason::AuxMissing => "aux-missing",
+            RebuildReason::RerunRequested(_) => "rerun-requested",
+            RebuildReason::BibliographyChanged => "bibliography-changed",
+            RebuildReason::IndexChanged => "index-changed",
+        };
+    }
+}
+
+impl Display for RebuildReason {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        match self {
+            RebuildReason::PdfMissing => write!(f, "pdf does not exist"),
+            RebuildReason::LastBuildFailed => write!(f, "last build has failed"),
+            RebuildReason::SourceChanged(path) => write!(f, "{} has changed", path.display()),
+            RebuildReason::AuxChanged => write!(f, "aux file has changed"),
+            RebuildReason::GeneratedChanged(path) => write!(f, "{} has changed", path.display()),
+            RebuildReason::AuxMissing => write!(f, "aux file did not exist"),
+            RebuildReason::RerunRequested(message) => {
+                write!(f, "latex asked for a rerun: {}", message)
+            }
+            RebuildReason::BibliographyChanged => write!(f, "bibliography has changed"),
+            RebuildReason::IndexChanged => write!(f, "index or glossaries have changed"),
+        }
+    }
+}
 
 /// An object that determine if a build is still needed
 ///
@@ -11,9 +73,121 @@ use std::path::PathBuf;
 ///   of project
 pub struct NeedsBuildChecker<'a> {
     pub project: &'a Project,
-    old_aux: Option<Vec<u8>>,
+    old_generated: GeneratedFiles,
     has_checked_sources: bool,
-    has_checked_aux: bool,
+    requested_build: Option<RebuildReason>,
+    /// The number of builds the checker has asked for
+    passes: usize,
+    /// The hashes of every state the generated files have been in
+    generated_hashes: Vec<String>,
+    /// The generated files before the last pass, to list the aux entries
+    /// that changed when the build is stopped
+    previous_generated: GeneratedFiles,
+}
+
+/// The content of the generated files in the bin directory
+type GeneratedFiles = BTreeMap<PathBuf, Vec<u8>>;
+
+/// Hash the content of all the generated files
+fn hash_generated(generated: &GeneratedFiles) -> String {
+    let mut content: Vec<u8> = Vec::new();
+
+    for (file, file_content) in generated {
+        content.extend_from_slice(file.to_string_lossy().as_bytes());
+        content.push(0);
+        content.extend_from_slice(file_content);
+        content.push(0);
+    }
+
+    return hash_content(&content);
+}
+
+/// The lines that are in only one of two versions of a file
+///
+/// # Arguments
+///
+/// - `prefix`: the prefix of the returned lines
+/// - `old_file`: the old content
+/// - `new_file`: the new content
+fn changed_entries(prefix: &str, old_file: &[u8], new_file: &[u8]) -> Vec<String> {
+    let old_file = String::from_utf8_lossy(old_file);
+    let new_file = String::from_utf8_lossy(new_file);
+    let old_lines: HashSet<&str> = old_file.lines().collect();
+    let new_lines: HashSet<&str> = new_file.lines().collect();
+
+    let mut seen_lines: HashSet<&str> = HashSet::new();
+    let mut entries: Vec<String> = Vec::new();
+
+    for line in old_file.lines().chain(new_file.lines()) {
+        if old_lines.contains(line) != new_lines.contains(line) && seen_lines.insert(line) {
+            entries.push(format!("{}{}", prefix, line));
+        }
+    }
+
+    return entries;
+}
+
+impl Project {
+    /// The aux file, and the aux files it `\@input`s, which is how
+    /// `\include` files write their own aux files
+    pub fn aux_files(&self) -> Vec<PathBuf> {
+        let mut files: Vec<PathBuf> = Vec::new();
+        let mut to_explore: Vec<PathBuf> = vec![PathBuf::from(self.aux())];
+
+        while let Some(aux) = to_explore.pop() {
+            let content = match read(&aux) {
+                Ok(content) => String::from_utf8_lossy(&content).into_owned(),
+                Err(_) => continue,
+            };
+
+            for line in content.lines() {
+                if let Some(input) = line.strip_prefix("\\@input{") {
+                    let input = aux.with_file_name(input.trim_end_matches('}'));
+
+                    if !files.contains(&input) && !to_explore.contains(&input) {
+                        to_explore.push(input);
+                    }
+                }
+            }
+
+            files.push(aux);
+        }
+
+        return files;
+    }
+
+    /// The files in the bin directory that latex reads back in the next pass:
+    /// the aux files, and the files with the `tracked_extensions` of the
+    /// project
+    pub fn generated_files(&self) -> Vec<PathBuf> {
+        let mut files = self.aux_files();
+
+        for extension in self.tracked_extensions() {
+            let file = self.aux().with_extension(extension);
+
+            if file.exists() {
+                files.push(file);
+            }
+        }
+
+        return files;
+    }
+
+    fn read_generated_files(&self) -> GeneratedFiles {
+        let mut generated = GeneratedFiles::new();
+
+        for file in self.generated_files() {
+            if let Ok(content) = read(&file) {
+                generated.insert(file, content);
+            }
+        }
+
+        return generated;
+    }
+}
+
+fn is_aux(file: &Path) -> bool {
+    return file.extension().map(|extension| extension == "aux") == Some(true);
 }
 
 impl<'a> NeedsBuildChecker<'a> {
@@ -23,84 +197,415 @@ impl<'a> NeedsBuildChecker<'a> {
     ///
     /// -
     pub fn new(project: &'a Project) -> NeedsBuildChecker<'a> {
-        let old_aux: Option<Vec<u8>> = match read(project.aux()) {
-            Ok(aux) => Some(aux),
-            _ => None,
-        };
+        let old_generated = project.read_generated_files();
+        let generated_hashes: Vec<String> = vec![hash_generated(&old_generated)];
 
         NeedsBuildChecker {
-            project: project,
-            old_aux: old_aux,
+            project,
+            previous_generated: old_generated.clone(),
+            old_generated,
             has_checked_sources: false,
-            has_checked_aux: false,
+            requested_build: None,
+            passes: 0,
+            generated_hashes,
         }
     }
 
+    /// Request another build regardless of the aux file, for example because
+    /// the bibliography has changed
+    ///
+    /// # Arguments
+    ///
+    /// - `reason`: the reason the build is needed
+    pub fn request_build(&mut self, reason: RebuildReason) {
+        self.requested_build = Some(reason);
+    }
+
     /// Determine if a build is needed
     ///
     /// # Returns
     ///
-    /// `true` is a build is needed
-    pub fn needs_build(&mut self) -> Result<bool, Error> {
+    /// - The reason a build is needed, `None` if no build is needed
+    /// - `Err(Error::TooManyPasses)` if the build has already taken
+    ///   `max_passes` passes
+    /// - `Err(Error::AuxOscillation)` if the aux file has returned to a
+    ///   previous state, so it will never stabilise
+    pub fn needs_build(&mut self) -> Result<Option<RebuildReason>, Error> {
+        let reason = self.check()?;
+
+        if reason.is_some() {
+            if self.passes >= self.project.max_passes() {
+                return Err(Error::TooManyPasses(
+                    self.passes,
+                    self.changed_entries(&self.previous_generated, &self.old_generated),
+                ));
+            }
+
+            self.passes += 1;
+        }
+
+        return Ok(reason);
+    }
+
+    /// The entries of the generated files that have changed between two
+    /// passes, the entries of a file other than the aux file are prefixed
+    /// with its path
+    fn changed_entries(&self, old: &GeneratedFiles, new: &GeneratedFiles) -> Vec<String> {
+        let mut entries: Vec<String> = Vec::new();
+
+        for (file, new_content) in new {
+            let old_content = match old.get(file) {
+                Some(old_content) if old_content != new_content => old_content,
+                _ => continue,
+            };
+
+            let prefix = if file == self.project.aux() {
+                String::new()
+            } else {
+                format!("{}: ", file.display())
+            };
+
+            entries.append(&mut changed_entries(&prefix, old_content, new_content));
+        }
+
+        return entries;
+    }
+
+    fn check(&mut self) -> Result<Option<RebuildReason>, Error> {
         if !self.has_checked_sources {
             self.has_checked_sources = true;
 
-            match metadata(self.project.pdf()) {
-                // if pdf exists, check to see of sources are newer than pdf
-                // if yes, rebuild
-                Ok(metadata) => {
-                    let pdf_modified = match metadata.modified() {
-                        Ok(modified) => modified,
-                        Err(error) => return Err(Error::IO(error)),
-                    };
+            let pdf_metadata = match metadata(self.project.pdf()) {
+                Ok(metadata) => metadata,
+                // if pdf does not exist, rebuild
+                Err(_) => {
+                    return Ok(Some(RebuildReason::PdfMissing));
+                }
+            };
+
+            if self.project.failure_file().exists() {
+                return Ok(Some(RebuildReason::LastBuildFailed));
+            }
+
+            let strategy = self.project.rebuild_strategy();
+
+            // content hashing ignores modification times, so that touching
+            // the pdf or a source does not lead to a build
+            let pdf_modified = match strategy {
+                RebuildStrategy::Hash => None,
+                _ => match pdf_metadata.modified() {
+                    Ok(modified) => Some(modified),
+                    Err(error) => {
+                        return Err(Error::IO(
+                            "read the modification time of",
+                            PathBuf::from(self.project.pdf()),
+                            error,
+                        ));
+                    }
+                },
+            };
+
+            let state = match strategy {
+                RebuildStrategy::Mtime => None,
+                _ => Some(BuildState::load(&self.project.state_file())),
+            };
 
-                    for file in self.project.files() {
+            for file in &self.project.dependencies() {
+                // if pdf exists, check to see of sources are newer than pdf
+                let is_newer = match pdf_modified {
+                    None => true,
+                    Some(pdf_modified) => {
                         let file_modified = match file.metadata() {
                             Ok(meta) => match meta.modified() {
                                 Ok(modified) => modified,
-                                Err(error) => return Err(Error::IO(error)),
+                                Err(error) => {
+                                    return Err(Error::IO(
+                                        "read the modification time of",
+                                        PathBuf::from(file),
+                                        error,
+                                    ));
+                                }
                             },
                             Err(_error) => return Err(Error::PathNotFound(PathBuf::from(file))),
                         };
 
-                        if pdf_modified < file_modified {
-                            return Ok(true);
-                        }
+                        pdf_modified < file_modified
                     }
+                };
 
-                    return Ok(false);
+                if !is_newer {
+                    continue;
                 }
-                // if pdf does not exist, rebuild
-                Err(_) => {
-                    return Ok(true);
+
+                // then check to see if the content has actually changed
+                let is_changed = match &state {
+                    Some(state) => !state.is_unchanged(file)?,
+                    None => true,
+                };
+
+                // if yes, rebuild
+                if is_changed {
+                    return Ok(Some(RebuildReason::SourceChanged(file.clone())));
                 }
-            };
+            }
+
+            return Ok(None);
         }
 
-        if !self.has_checked_aux {
-            self.has_checked_aux = true;
-
-            match &self.old_aux {
-                // originally have an aux file
-                Some(old_aux) => {
-                    match read(self.project.aux()) {
-                        // Cannot open the new aux file, so return false
-                        // to be safe
-                        Err(_) => {
-                            return Ok(false);
-                        }
-                        Ok(ref new_aux) => return Ok(!(new_aux == old_aux)),
-                    }
-                }
-                // we did not originally have a aux file, it means the project
-                // has just been built for the first time, therefore, needs a
-                // build
-                None => {
-                    return Ok(true);
-                }
+        // the generated files are compared after every pass, not only after
+        // the first one: bibtex runs after the first pass, the second pass
+        // reads the bbl and writes the `\bibcite`s to the aux file, and only a
+        // third pass resolves the citations. Comparing once would stop after
+        // the second pass with every citation still undefined
+        //
+        // the generated files after the last build, to be compared against
+        // the generated files before it
+        let new_generated = self.project.read_generated_files();
+
+        // files that do not exist any more are not read by the next pass, so
+        // only the files that exist are compared
+        let changed_files: Vec<&PathBuf> = new_generated
+            .iter()
+            .filter(|(file, content)| self.old_generated.get(*file) != Some(*content))
+            .map(|(file, _content)| file)
+            .collect();
+
+        let reason = if !self.old_generated.contains_key(self.project.aux())
+            && new_generated.contains_key(self.project.aux())
+        {
+            // we did not originally have a aux file, it means the project
+            // has just been built for the first time, therefore, needs a
+            // build
+            Some(RebuildReason::AuxMissing)
+        } else if changed_files.iter().any(|file| is_aux(file)) {
+            Some(RebuildReason::AuxChanged)
+        } else {
+            changed_files
+                .first()
+                .map(|file| RebuildReason::GeneratedChanged(PathBuf::from(file)))
+        };
+
+        let hash = hash_generated(&new_generated);
+
+        // the generated files have changed back to a previous state rather
+        // than to the state before the last pass, so they will keep changing
+        if !changed_files.is_empty() && self.generated_hashes.contains(&hash) {
+            return Err(Error::AuxOscillation(
+                self.changed_entries(&self.old_generated, &new_generated),
+            ));
+        }
+
+        self.generated_hashes.push(hash);
+        self.previous_generated = replace(&mut self.old_generated, new_generated);
+
+        let requested_build = self.requested_build.take();
+
+        return Ok(reason.or(requested_build));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::{create_dir, write};
+
+    /// A project in a temporary directory, with an entry and a bin directory
+    fn project(dir: &Path, config: &str) -> Project {
+        let path = dir.join("latexproject.json");
+        write(&path, config).unwrap();
+        write(dir.join("index.tex"), "\\documentclass{article}").unwrap();
+        create_dir(dir.join("bin")).unwrap();
+
+        let mut project = Project::load(&path).unwrap();
+        project.use_root_path(dir);
+
+        return project;
+    }
+
+    #[test]
+    fn missing_pdf_needs_build() {
+        let dir = tempfile::tempdir().unwrap();
+        let project = project(dir.path(), "{}");
+        let mut checker = NeedsBuildChecker::new(&project);
+
+        assert_eq!(
+            checker.needs_build().unwrap(),
+            Some(RebuildReason::PdfMissing)
+        );
+    }
+
+    #[test]
+    fn changed_source_needs_build() {
+        let dir = tempfile::tempdir().unwrap();
+        let project = project(dir.path(), r#"{ "rebuild_strategy": "hash" }"#);
+        write(project.pdf(), "pdf").unwrap();
+        project.save_state().unwrap();
+
+        assert_eq!(
+            NeedsBuildChecker::new(&project).needs_build().unwrap(),
+            None
+        );
+
+        write(project.entry(), "\\documentclass{book}").unwrap();
+
+        assert_eq!(
+            NeedsBuildChecker::new(&project).needs_build().unwrap(),
+            Some(RebuildReason::SourceChanged(PathBuf::from(project.entry())))
+        );
+    }
+
+    #[test]
+    fn failed_build_needs_build() {
+        let dir = tempfile::tempdir().unwrap();
+        let project = project(dir.path(), r#"{ "rebuild_strategy": "hash" }"#);
+        write(project.pdf(), "pdf").unwrap();
+        project.save_state().unwrap();
+        project.save_failure(true).unwrap();
+
+        assert_eq!(
+            NeedsBuildChecker::new(&project).needs_build().unwrap(),
+            Some(RebuildReason::LastBuildFailed)
+        );
+    }
+
+    #[test]
+    fn changed_aux_needs_another_pass() {
+        let dir = tempfile::tempdir().unwrap();
+        let project = project(dir.path(), "{}");
+        let mut checker = NeedsBuildChecker::new(&project);
+        checker.needs_build().unwrap();
+
+        write(project.aux(), "\\relax").unwrap();
+        assert_eq!(
+            checker.needs_build().unwrap(),
+            Some(RebuildReason::AuxMissing)
+        );
+
+        write(project.aux(), "\\relax\n\\newlabel{a}{{1}{1}}").unwrap();
+        assert_eq!(
+            checker.needs_build().unwrap(),
+            Some(RebuildReason::AuxChanged)
+        );
+
+        assert_eq!(checker.needs_build().unwrap(), None);
+    }
+
+    #[test]
+    fn changed_include_aux_needs_another_pass() {
+        let dir = tempfile::tempdir().unwrap();
+        let project = project(dir.path(), "{}");
+        let chapter = project.bin().join("chapter.aux");
+        write(project.aux(), "\\relax\n\\@input{chapter.aux}").unwrap();
+        write(&chapter, "\\relax").unwrap();
+
+        let mut checker = NeedsBuildChecker::new(&project);
+        checker.needs_build().unwrap();
+
+        write(&chapter, "\\relax\n\\newlabel{a}{{1}{1}}").unwrap();
+        assert_eq!(
+            checker.needs_build().unwrap(),
+            Some(RebuildReason::AuxChanged)
+        );
+    }
+
+    #[test]
+    fn changed_generated_file_needs_another_pass() {
+        let dir = tempfile::tempdir().unwrap();
+        let project = project(dir.path(), "{}");
+        let toc = project.aux().with_extension("toc");
+        write(project.aux(), "\\relax").unwrap();
+        write(&toc, "").unwrap();
+
+        let mut checker = NeedsBuildChecker::new(&project);
+        checker.needs_build().unwrap();
+
+        write(&toc, "\\contentsline {section}{Introduction}{1}").unwrap();
+        assert_eq!(
+            checker.needs_build().unwrap(),
+            Some(RebuildReason::GeneratedChanged(toc))
+        );
+    }
+
+    #[test]
+    fn requested_build_needs_another_pass() {
+        let dir = tempfile::tempdir().unwrap();
+        let project = project(dir.path(), "{}");
+        write(project.aux(), "\\relax").unwrap();
+
+        let mut checker = NeedsBuildChecker::new(&project);
+        checker.needs_build().unwrap();
+
+        let reason = RebuildReason::RerunRequested(String::from("Label(s) may have changed."));
+        checker.request_build(reason.clone());
+        assert_eq!(checker.needs_build().unwrap(), Some(reason));
+
+        assert_eq!(checker.needs_build().unwrap(), None);
+    }
+
+    #[test]
+    fn passes_are_capped() {
+        let dir = tempfile::tempdir().unwrap();
+        let project = project(dir.path(), r#"{ "max_passes": 2 }"#);
+        let mut checker = NeedsBuildChecker::new(&project);
+        checker.needs_build().unwrap();
+
+        write(project.aux(), "\\newlabel{a}{{1}{1}}").unwrap();
+        assert_eq!(
+            checker.needs_build().unwrap(),
+            Some(RebuildReason::AuxMissing)
+        );
+
+        write(
+            project.aux(),
+            "\\newlabel{a}{{1}{1}}\n\\newlabel{b}{{2}{1}}",
+        )
+        .unwrap();
+        match checker.needs_build() {
+            Err(Error::TooManyPasses(passes, entries)) => {
+                assert_eq!(passes, 2);
+                assert_eq!(entries, vec![String::from("\\newlabel{b}{{2}{1}}")]);
             }
+            result => panic!("unexpected result {:?}", result),
         }
+    }
+
+    #[test]
+    fn oscillating_aux_is_detected() {
+        let dir = tempfile::tempdir().unwrap();
+        let project = project(dir.path(), "{}");
+        let mut checker = NeedsBuildChecker::new(&project);
+        checker.needs_build().unwrap();
+
+        write(project.aux(), "\\newlabel{x}{{a}{1}}").unwrap();
+        assert_eq!(
+            checker.needs_build().unwrap(),
+            Some(RebuildReason::AuxMissing)
+        );
+
+        write(project.aux(), "\\newlabel{x}{{b}{1}}").unwrap();
+        assert_eq!(
+            checker.needs_build().unwrap(),
+            Some(RebuildReason::AuxChanged)
+        );
+
+        write(project.aux(), "\\newlabel{x}{{a}{1}}").unwrap();
+        match checker.needs_build() {
+            Err(Error::AuxOscillation(entries)) => assert_eq!(
+                entries,
+                vec![
+                    String::from("\\newlabel{x}{{b}{1}}"),
+                    String::from("\\newlabel{x}{{a}{1}}"),
+                ]
+            ),
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
 
-        return Ok(false);
+    #[test]
+    fn changed_entries_are_unique() {
+        assert_eq!(
+            changed_entries("index.toc: ", b"a\nb\nb", b"b\nc\nc"),
+            vec![String::from("index.toc: a"), String::from("index.toc: c")]
+        );
     }
 }
@@ -0,0 +1,651 @@
+use super::{Bibliography, EngineOutput, Error, Index, Interaction, RebuildStrategy, ShellEscape};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::read;
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+
+/// The project specification, as written in `latexproject.json`,
+/// `latexproject.toml` or `latexproject.yaml`
+///
+/// # Discussion
+///
+/// Unknown keys are rejected so that typos such as `"include"` do not go
+/// unnoticed. Keys that have their default values are not written out, except
+/// for `latex`, `bin`, `entry` and `includes`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// The `latex` program used, or `auto` to read it from a
+    /// `% !TEX program = ...` comment in the entry
+    pub latex: String,
+    /// The output directory
+    pub bin: PathBuf,
+    /// The entry latex file
+    pub entry: PathBuf,
+    /// The name of the output files, the name of the entry if not set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output: Option<String>,
+    /// The include files, directories and glob patterns
+    pub includes: Vec<PathBuf>,
+    /// The `.gitignore`-style patterns of the files not to include from the
+    /// directories and glob patterns of `includes`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub excludes: Vec<String>,
+    /// The program used to process the bibliography, detected if not set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bibliography: Option<Bibliography>,
+    /// The program used to process the index
+    #[serde(skip_serializing_if = "is_default_index")]
+    pub index: Index,
+    /// The style file passed to the index program
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub index_style: Option<PathBuf>,
+    /// The style file passed to makeglossaries
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub glossary_style: Option<PathBuf>,
+    /// How to determine if the sources have changed
+    #[serde(skip_serializing_if = "is_default_rebuild_strategy")]
+    pub rebuild_strategy: RebuildStrategy,
+    /// The maximum number of latex passes in one build
+    #[serde(skip_serializing_if = "is_default_max_passes")]
+    pub max_passes: NonZeroUsize,
+    /// The extensions of the generated files that need another pass when
+    /// they change, in addition to the aux files
+    #[serde(skip_serializing_if = "is_default_tracked_extensions")]
+    pub tracked_extensions: Vec<String>,
+    /// How latex interacts with the user on errors
+    #[serde(skip_serializing_if = "is_default_interaction")]
+    pub interaction: Interaction,
+    /// Write a synctex file for jumping between the source and the pdf
+    #[serde(skip_serializing_if = "is_false")]
+    pub synctex: bool,
+    /// Whether the document can run external programs, the default of the
+    /// tex distribution if not set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shell_escape: Option<ShellEscape>,
+    /// Print errors as `file:line: error`
+    #[serde(skip_serializing_if = "is_false")]
+    pub file_line_error: bool,
+    /// Which lines of the output of latex are shown while it runs. If not
+    /// set, the progress is shown, and the full output with `-vv`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub engine_output: Option<EngineOutput>,
+    /// The number of seconds a latex pass can take before it is killed, no
+    /// limit if not set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<u64>,
+    /// The extra arguments passed to `latex`, before the entry
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub args: Vec<String>,
+    /// The macros defined with `\def` before the entry is input, by name
+    /// without the backslash
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub defines: BTreeMap<String, String>,
+    /// The named sets of settings a build can be made with, such as `draft`
+    /// and `final`
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub profiles: BTreeMap<String, Profile>,
+    /// The named documents built from the project. When there are targets,
+    /// `entry` is not built and the other keys are shared by the targets
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub targets: BTreeMap<String, Target>,
+}
+
+/// A set of settings a build can be made with, selected with `--profile`
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Profile {
+    /// The `latex` program used, the one of the project if not set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub latex: Option<String>,
+    /// The arguments passed to `latex` in addition to the `args` of the
+    /// project
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub args: Vec<String>,
+    /// The output directory, a directory named after the profile inside the
+    /// `bin` of the project if not set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bin: Option<PathBuf>,
+    // toml writes tables after the values, so `defines` comes last
+    /// The macros defined in addition to the `defines` of the project,
+    /// replacing the ones with the same name
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub defines: BTreeMap<String, String>,
+}
+
+/// A document built from a project that has several, such as a paper and
+/// the slides presenting it
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Target {
+    /// The entry latex file
+    pub entry: PathBuf,
+    /// The `latex` program used, the one of the project if not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub latex: Option<String>,
+    /// The name of the output files, the name of the target if not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output: Option<String>,
+    /// The files, directories and glob patterns included in addition to the
+    /// `includes` of the project
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub includes: Vec<PathBuf>,
+}
+
+/// Determine if every `{` of tex code is closed by a `}`
+fn has_balanced_braces(code: &str) -> bool {
+    let mut depth: usize = 0;
+
+    for c in code.chars() {
+        match c {
+            '{' => depth += 1,
+            '}' => match depth.checked_sub(1) {
+                Some(new_depth) => depth = new_depth,
+                None => return false,
+            },
+            _ => {}
+        }
+    }
+
+    return depth == 0;
+}
+
+/// The default of `max_passes`
+const DEFAULT_MAX_PASSES: usize = 5;
+
+/// The default of `tracked_extensions`: table of contents, list of figures,
+/// list of tables, hyperref bookmarks and beamer navigation
+const DEFAULT_TRACKED_EXTENSIONS: [&str; 5] = ["toc", "lof", "lot", "out", "nav"];
+
+fn default_max_passes() -> NonZeroUsize {
+    return NonZeroUsize::new(DEFAULT_MAX_PASSES).unwrap();
+}
+
+fn default_tracked_extensions() -> Vec<String> {
+    return DEFAULT_TRACKED_EXTENSIONS
+        .iter()
+        .map(|extension| String::from(*extension))
+        .collect();
+}
+
+fn is_false(value: &bool) -> bool {
+    return !*value;
+}
+
+fn is_default_interaction(interaction: &Interaction) -> bool {
+    return *interaction == Interaction::Nonstopmode;
+}
+
+fn is_default_index(index: &Index) -> bool {
+    return *index == Index::Makeindex;
+}
+
+fn is_default_rebuild_strategy(rebuild_strategy: &RebuildStrategy) -> bool {
+    return *rebuild_strategy == RebuildStrategy::Mtime;
+}
+
+fn is_default_max_passes(max_passes: &NonZeroUsize) -> bool {
+    return *max_passes == default_max_passes();
+}
+
+fn is_default_tracked_extensions(tracked_extensions: &[String]) -> bool {
+    return tracked_extensions == default_tracked_extensions().as_slice();
+}
+
+impl Default for Config {
+    /// Create a config with the following default values
+    ///
+    /// - `latex`: `pdflatex`
+    /// - `bin`: `bin`
+    /// - `entry`: `index.tex`
+    /// - `includes`: []
+    /// - `excludes`: []
+    /// - `max_passes`: 5
+    /// - `tracked_extensions`: `toc`, `lof`, `lot`, `out`, `nav`
+    /// - `interaction`: `nonstopmode`
+    /// - `synctex`: `false`
+    /// - `file_line_error`: `false`
+    /// - `args`: []
+    /// - `defines`: {}
+    /// - `profiles`: {}
+    /// - `targets`: {}
+    fn default() -> Config {
+        Config {
+            latex: String::from("pdflatex"),
+            bin: PathBuf::from("bin"),
+            entry: PathBuf::from("index.tex"),
+            output: None,
+            includes: Vec::new(),
+            excludes: Vec::new(),
+            bibliography: None,
+            index: Index::Makeindex,
+            index_style: None,
+            glossary_style: None,
+            rebuild_strategy: RebuildStrategy::Mtime,
+            max_passes: default_max_passes(),
+            tracked_extensions: default_tracked_extensions(),
+            interaction: Interaction::Nonstopmode,
+            synctex: false,
+            shell_escape: None,
+            file_line_error: false,
+            engine_output: None,
+            timeout: None,
+            args: Vec::new(),
+            defines: BTreeMap::new(),
+            profiles: BTreeMap::new(),
+            targets: BTreeMap::new(),
+        }
+    }
+}
+
+/// The file formats a config can be written in
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ConfigFormat {
+    Json,
+    Toml,
+    Yaml,
+}
+
+/// The names of the config files looked up when no config is specified, in
+/// order of preference
+pub const DEFAULT_CONFIG_NAMES: [&str; 4] = [
+    "latexproject.json",
+    "latexproject.toml",
+    "latexproject.yaml",
+    "latexproject.yml",
+];
+
+impl ConfigFormat {
+    /// Parse the name of a format, as passed to `latexbuild new --format`
+    pub fn from_name(name: &str) -> Option<ConfigFormat> {
+        return match name {
+            "json" => Some(ConfigFormat::Json),
+            "toml" => Some(ConfigFormat::Toml),
+            "yaml" | "yml" => Some(ConfigFormat::Yaml),
+            _ => None,
+        };
+    }
+
+    /// Determine the format of a config from the extension of its path
+    pub fn from_path(path: &Path) -> Result<ConfigFormat, Error> {
+        let format = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .and_then(ConfigFormat::from_name);
+
+        return match format {
+            Some(format) => Ok(format),
+            None => Err(Error::WrongConfigFormat(format!(
+                "{} should have a json, toml or yaml extension",
+                path.display()
+            ))),
+        };
+    }
+
+    /// The extension of config files in the format
+    pub fn extension(&self) -> &'static str {
+        return match self {
+            ConfigFormat::Json => "json",
+            ConfigFormat::Toml => "toml",
+            ConfigFormat::Yaml => "yaml",
+        };
+    }
+}
+
+impl Config {
+    /// Load a config, the parser is picked by the extension of the path. An
+    /// empty file is the same as an empty object
+    ///
+    /// # Arguments
+    ///
+    /// - `path`: the path to the config
+    pub fn load<P: AsRef<Path>>(path: &P) -> Result<Config, Error> {
+        let format = ConfigFormat::from_path(path.as_ref())?;
+
+        let file_content = match read(path) {
+            Ok(raw_content) => match String::from_utf8(raw_content) {
+                Ok(s) => s,
+                Err(_) => return Err(Error::Encoding),
+            },
+            Err(_) => {
+                return Err(Error::PathNotFound(PathBuf::from(path.as_ref())));
+            }
+        };
+
+        if file_content.trim().is_empty() {
+            return Ok(Config::default());
+        }
+
+        return match format {
+            ConfigFormat::Json => match serde_json::from_str(&file_content) {
+                Ok(config) => Ok(config),
+                Err(error) => Err(Error::JsonParsing(PathBuf::from(path.as_ref()), error)),
+            },
+            ConfigFormat::Toml => match toml::from_str(&file_content) {
+                Ok(config) => Ok(config),
+                Err(error) => Err(Error::TomlParsing(PathBuf::from(path.as_ref()), error)),
+            },
+            ConfigFormat::Yaml => match serde_yaml::from_str(&file_content) {
+                Ok(config) => Ok(config),
+                Err(error) => Err(Error::YamlParsing(PathBuf::from(path.as_ref()), error)),
+            },
+        };
+    }
+
+    /// Serialize the config
+    ///
+    /// # Arguments
+    ///
+    /// - `format`: the format to serialize to
+    pub fn to_string(&self, format: ConfigFormat) -> Result<String, Error> {
+        return match format {
+            ConfigFormat::Json => match serde_json::to_string_pretty(self) {
+                Ok(s) => Ok(s),
+                Err(error) => Err(Error::ConfigSerialization(Box::new(error))),
+            },
+            ConfigFormat::Toml => match toml::to_string_pretty(self) {
+                Ok(s) => Ok(s),
+                Err(error) => Err(Error::ConfigSerialization(Box::new(error))),
+            },
+            ConfigFormat::Yaml => match serde_yaml::to_string(self) {
+                Ok(s) => Ok(s),
+                Err(error) => Err(Error::ConfigSerialization(Box::new(error))),
+            },
+        };
+    }
+
+    /// Check the values that parse but cannot be built with
+    ///
+    /// # Returns
+    ///
+    /// `Err(Error::InvalidConfig)` with the first invalid value
+    pub fn validate(&self) -> Result<(), Error> {
+        for (name, value) in &self.defines {
+            // the defines are passed as `\def\name{value}`, the name of a
+            // macro is only made of letters
+            if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphabetic()) {
+                return Err(Error::InvalidConfig(format!(
+                    "the name of define {:?} should only be made of letters",
+                    name
+                )));
+            }
+
+            // `%` would comment out the rest of the argument and `#` is a
+            // macro parameter
+            if value.contains(['%', '#']) || !has_balanced_braces(value) {
+                return Err(Error::InvalidConfig(format!(
+                    "the value of define {} should have balanced braces and no % or #",
+                    name
+                )));
+            }
+        }
+
+        return Ok(());
+    }
+
+    /// The config of a target: the config of the project with the entry,
+    /// `latex`, output name and includes of the target
+    ///
+    /// # Arguments
+    ///
+    /// - `name`: the name of the target
+    ///
+    /// # Returns
+    ///
+    /// `Err(Error::UnknownTarget)` if the config has no target named `name`
+    pub fn target(&self, name: &str) -> Result<Config, Error> {
+        let target = match self.targets.get(name) {
+            Some(target) => target,
+            None => return Err(Error::UnknownTarget(String::from(name))),
+        };
+
+        let mut config = self.clone();
+        config.targets = BTreeMap::new();
+        config.entry = target.entry.clone();
+        config.output = Some(target.output.clone().unwrap_or_else(|| String::from(name)));
+        config.includes.extend(target.includes.iter().cloned());
+
+        if let Some(latex) = &target.latex {
+            config.latex = latex.clone();
+        }
+
+        return Ok(config);
+    }
+
+    /// The config of a profile: the config of the project with the settings
+    /// of the profile applied
+    ///
+    /// # Arguments
+    ///
+    /// - `name`: the name of the profile
+    ///
+    /// # Returns
+    ///
+    /// `Err(Error::UnknownProfile)` if the config has no profile named `name`
+    pub fn profile(&self, name: &str) -> Result<Config, Error> {
+        let profile = match self.profiles.get(name) {
+            Some(profile) => profile,
+            None => return Err(Error::UnknownProfile(String::from(name))),
+        };
+
+        let mut config = self.clone();
+        config.profiles = BTreeMap::new();
+        config.args.extend(profile.args.iter().cloned());
+        config.defines.extend(profile.defines.clone());
+
+        if let Some(latex) = &profile.latex {
+            config.latex = latex.clone();
+        }
+
+        // each profile has its own bin directory, so that switching profiles
+        // does not make the aux files of the other profile stale
+        config.bin = match &profile.bin {
+            Some(bin) => bin.clone(),
+            None => self.bin.join(name),
+        };
+
+        return Ok(config);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::write;
+
+    /// A config that sets every key, with profiles and targets
+    fn full_config() -> Config {
+        let mut defines = BTreeMap::new();
+        defines.insert(String::from("draft"), String::from("1"));
+
+        let mut profiles = BTreeMap::new();
+        profiles.insert(
+            String::from("final"),
+            Profile {
+                latex: Some(String::from("lualatex")),
+                args: vec![String::from("-halt-on-error")],
+                defines: defines.clone(),
+                bin: Some(PathBuf::from("out")),
+            },
+        );
+
+        let mut targets = BTreeMap::new();
+        targets.insert(
+            String::from("slides"),
+            Target {
+                entry: PathBuf::from("slides.tex"),
+                latex: Some(String::from("xelatex")),
+                output: Some(String::from("talk")),
+                includes: vec![PathBuf::from("figures")],
+            },
+        );
+
+        return Config {
+            latex: String::from("xelatex"),
+            bin: PathBuf::from("build"),
+            entry: PathBuf::from("paper.tex"),
+            output: Some(String::from("paper")),
+            includes: vec![PathBuf::from("chapters/*.tex")],
+            excludes: vec![String::from("draft-*.tex")],
+            bibliography: Some(Bibliography::Biber),
+            index: Index::Xindy,
+            index_style: Some(PathBuf::from("index.xdy")),
+            glossary_style: Some(PathBuf::from("glossary.ist")),
+            rebuild_strategy: RebuildStrategy::Hash,
+            max_passes: NonZeroUsize::new(3).unwrap(),
+            tracked_extensions: vec![String::from("toc")],
+            interaction: Interaction::Batchmode,
+            synctex: true,
+            shell_escape: Some(ShellEscape::Restricted),
+            file_line_error: true,
+            engine_output: Some(EngineOutput::Full),
+            timeout: Some(60),
+            args: vec![String::from("-8bit")],
+            defines,
+            profiles,
+            targets,
+        };
+    }
+
+    fn round_trip(config: &Config, format: ConfigFormat) -> Config {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir
+            .path()
+            .join(format!("latexproject.{}", format.extension()));
+        write(&path, config.to_string(format).unwrap()).unwrap();
+
+        return Config::load(&path).unwrap();
+    }
+
+    #[test]
+    fn round_trip_json() {
+        assert_eq!(
+            round_trip(&full_config(), ConfigFormat::Json),
+            full_config()
+        );
+        assert_eq!(
+            round_trip(&Config::default(), ConfigFormat::Json),
+            Config::default()
+        );
+    }
+
+    #[test]
+    fn round_trip_toml() {
+        assert_eq!(
+            round_trip(&full_config(), ConfigFormat::Toml),
+            full_config()
+        );
+        assert_eq!(
+            round_trip(&Config::default(), ConfigFormat::Toml),
+            Config::default()
+        );
+    }
+
+    #[test]
+    fn round_trip_yaml() {
+        assert_eq!(
+            round_trip(&full_config(), ConfigFormat::Yaml),
+            full_config()
+        );
+        assert_eq!(
+            round_trip(&Config::default(), ConfigFormat::Yaml),
+            Config::default()
+        );
+    }
+
+    #[test]
+    fn toml_with_tables() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("latexproject.toml");
+        write(
+            &path,
+            r#"
+latex = "pdflatex"
+
+[defines]
+draft = "1"
+
+[profiles.final]
+args = ["-halt-on-error"]
+
+[profiles.final.defines]
+draft = "0"
+
+[targets.paper]
+entry = "paper.tex"
+
+[targets.slides]
+entry = "slides.tex"
+latex = "xelatex"
+"#,
+        )
+        .unwrap();
+
+        let config = Config::load(&path).unwrap();
+        assert_eq!(config.targets.len(), 2);
+
+        let slides = config.target("slides").unwrap();
+        assert_eq!(slides.entry, PathBuf::from("slides.tex"));
+        assert_eq!(slides.latex, "xelatex");
+        assert_eq!(slides.output, Some(String::from("slides")));
+
+        let final_config = config.profile("final").unwrap();
+        assert_eq!(final_config.defines["draft"], "0");
+        assert_eq!(final_config.bin, PathBuf::from("bin/final"));
+        assert_eq!(final_config.args, vec![String::from("-halt-on-error")]);
+    }
+
+    #[test]
+    fn empty_file_is_default() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("latexproject.yaml");
+        write(&path, "\n").unwrap();
+
+        assert_eq!(Config::load(&path).unwrap(), Config::default());
+    }
+
+    fn with_define(name: &str, value: &str) -> Config {
+        let mut config = Config::default();
+        config
+            .defines
+            .insert(String::from(name), String::from(value));
+
+        return config;
+    }
+
+    #[test]
+    fn valid_defines() {
+        assert!(with_define("draft", "1").validate().is_ok());
+        assert!(with_define("title", "\\textbf{A {nested} title}")
+            .validate()
+            .is_ok());
+    }
+
+    #[test]
+    fn invalid_defines() {
+        for (name, value) in &[
+            ("", "1"),
+            ("draft1", "1"),
+            ("my_draft", "1"),
+            ("draft", "100%"),
+            ("draft", "#1"),
+            ("draft", "{"),
+            ("draft", "}{"),
+        ] {
+            assert!(matches!(
+                with_define(name, value).validate(),
+                Err(Error::InvalidConfig(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn unknown_key_is_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("latexproject.json");
+        write(&path, r#"{ "include": ["a.tex"] }"#).unwrap();
+
+        assert!(matches!(Config::load(&path), Err(Error::JsonParsing(_, _))));
+    }
+}
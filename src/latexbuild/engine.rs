@@ -0,0 +1,259 @@
+use serde::{Deserialize, Serialize};
+use std::fs::read;
+use std::path::Path;
+
+/// The value of `latex` that reads the engine from the entry
+pub const AUTO_ENGINE: &str = "auto";
+
+/// The programs that typeset a project
+#[derive(Clone, Debug, PartialEq)]
+pub enum Engine {
+    Pdflatex,
+    Xelatex,
+    Lualatex,
+    /// latex, which writes a dvi converted with `dvipdfmx`
+    Latex,
+    /// platex, which writes a dvi converted with `dvipdfmx`
+    Platex,
+    /// uplatex, which writes a dvi converted with `dvipdfmx`
+    Uplatex,
+    /// tectonic, which makes the passes and runs bibtex itself
+    Tectonic,
+    /// Any other program, run with the same arguments as pdflatex
+    Custom(String),
+}
+
+impl Engine {
+    /// The engine run by a program
+    ///
+    /// # Arguments
+    ///
+    /// - `name`: the name of the program
+    pub fn from_name(name: &str) -> Engine {
+        return match name {
+            "pdflatex" => Engine::Pdflatex,
+            "xelatex" => Engine::Xelatex,
+            "lualatex" => Engine::Lualatex,
+            "latex" => Engine::Latex,
+            "platex" => Engine::Platex,
+            "uplatex" => Engine::Uplatex,
+            "tectonic" => Engine::Tectonic,
+            _ => Engine::Custom(String::from(name)),
+        };
+    }
+
+    /// The engine set by `latex` in the config
+    ///
+    /// # Arguments
+    ///
+    /// - `name`: the value of `latex`
+    /// - `entry`: the entry, where the engine is read from if `name` is
+    ///   `auto`
+    ///
+    /// # Returns
+    ///
+    /// The engine, pdflatex if `name` is `auto` and the entry does not name
+    /// one
+    pub fn from_config(name: &str, entry: &Path) -> Engine {
+        if name != AUTO_ENGINE {
+            return Engine::from_name(name);
+        }
+
+        return Engine::detect(entry).unwrap_or(Engine::Pdflatex);
+    }
+
+    /// Read the engine from a `% !TEX program = xelatex` or
+    /// `% !TEX TS-program = xelatex` comment at the top of a file
+    ///
+    /// # Arguments
+    ///
+    /// - `entry`: the file
+    pub fn detect(entry: &Path) -> Option<Engine> {
+        let content = match read(entry) {
+            Ok(content) => String::from_utf8_lossy(&content).into_owned(),
+            Err(_) => return None,
+        };
+
+        for line in content.lines() {
+            let line = line.trim();
+
+            if line.is_empty() {
+                continue;
+            }
+
+            // magic comments are only read before the first line of code
+            let comment = match line.strip_prefix('%') {
+                Some(comment) => comment.trim_start().to_ascii_lowercase(),
+                None => break,
+            };
+
+            let directive = match comment.strip_prefix("!tex") {
+                Some(directive) => directive.trim_start(),
+                None => continue,
+            };
+
+            let value = match directive
+                .strip_prefix("ts-program")
+                .or_else(|| directive.strip_prefix("program"))
+            {
+                Some(value) => value.trim_start(),
+                None => continue,
+            };
+
+            if let Some(program) = value.strip_prefix('=') {
+                return Some(Engine::from_name(program.trim()));
+            }
+        }
+
+        return None;
+    }
+
+    /// The name of the program
+    pub fn name(&self) -> &str {
+        return match self {
+            Engine::Pdflatex => "pdflatex",
+            Engine::Xelatex => "xelatex",
+            Engine::Lualatex => "lualatex",
+            Engine::Latex => "latex",
+            Engine::Platex => "platex",
+            Engine::Uplatex => "uplatex",
+            Engine::Tectonic => "tectonic",
+            Engine::Custom(name) => name,
+        };
+    }
+
+    /// The program converting the dvi written by the engine into a pdf,
+    /// `None` if the engine writes a pdf
+    pub fn converter(&self) -> Option<&'static str> {
+        return match self {
+            Engine::Latex | Engine::Platex | Engine::Uplatex => Some("dvipdfmx"),
+            _ => None,
+        };
+    }
+
+    /// Determine if one run of the engine makes all the passes and runs the
+    /// bibliography and index programs, so latexbuild does not have to
+    pub fn runs_to_completion(&self) -> bool {
+        return *self == Engine::Tectonic;
+    }
+}
+
+/// How the engine interacts with the user on errors
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Interaction {
+    /// Print nothing to the terminal and never stop on errors
+    Batchmode,
+    /// Print to the terminal but never stop on errors
+    Nonstopmode,
+    /// Stop on errors, but not on missing files
+    Scrollmode,
+    /// Stop on every error, the default of latex
+    Errorstopmode,
+}
+
+impl Interaction {
+    pub fn name(&self) -> &'static str {
+        return match self {
+            Interaction::Batchmode => "batchmode",
+            Interaction::Nonstopmode => "nonstopmode",
+            Interaction::Scrollmode => "scrollmode",
+            Interaction::Errorstopmode => "errorstopmode",
+        };
+    }
+}
+
+/// Whether the document can run external programs with `\write18`, as
+/// needed by packages such as minted
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ShellEscape {
+    /// No program can be run
+    Off,
+    /// Only the programs allowed by the tex distribution can be run
+    Restricted,
+    /// Any program can be run
+    On,
+}
+
+/// Which lines of the output of latex are logged while it runs
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EngineOutput {
+    /// Every line
+    Full,
+    /// The lines with page counters such as `[12]`, warnings and errors
+    Progress,
+}
+
+impl EngineOutput {
+    /// Determine if a line of the output of latex is logged
+    pub fn shows(&self, line: &str) -> bool {
+        if *self == EngineOutput::Full {
+            return true;
+        }
+
+        let has_page_counter = line
+            .match_indices('[')
+            .any(|(index, _)| line[index + 1..].starts_with(|c: char| c.is_ascii_digit()));
+
+        return has_page_counter
+            || line.starts_with('!')
+            || line.contains("Warning")
+            || line.contains(": error:");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::write;
+
+    fn detect(content: &str) -> Option<Engine> {
+        let dir = tempfile::tempdir().unwrap();
+        let entry = dir.path().join("index.tex");
+        write(&entry, content).unwrap();
+
+        return Engine::detect(&entry);
+    }
+
+    #[test]
+    fn detect_magic_comments() {
+        assert_eq!(detect("% !TEX program = xelatex\n"), Some(Engine::Xelatex));
+        assert_eq!(
+            detect("%!TeX TS-program=lualatex\n"),
+            Some(Engine::Lualatex)
+        );
+        assert_eq!(
+            detect("\n% !TEX encoding = UTF-8\n% !TEX program = tectonic\n"),
+            Some(Engine::Tectonic)
+        );
+        assert_eq!(
+            detect("% !TEX program = mylatex\n"),
+            Some(Engine::Custom(String::from("mylatex")))
+        );
+    }
+
+    #[test]
+    fn detect_stops_at_the_first_line_of_code() {
+        assert_eq!(
+            detect("\\documentclass{article}\n% !TEX program = xelatex\n"),
+            None
+        );
+        assert_eq!(detect("% a comment\n"), None);
+    }
+
+    #[test]
+    fn from_config_reads_the_entry_only_for_auto() {
+        let dir = tempfile::tempdir().unwrap();
+        let entry = dir.path().join("index.tex");
+        write(&entry, "% !TEX program = xelatex\n").unwrap();
+
+        assert_eq!(Engine::from_config("auto", &entry), Engine::Xelatex);
+        assert_eq!(Engine::from_config("lualatex", &entry), Engine::Lualatex);
+        assert_eq!(
+            Engine::from_config("auto", &dir.path().join("missing.tex")),
+            Engine::Pdflatex
+        );
+    }
+}
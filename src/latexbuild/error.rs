@@ -1,49 +1,170 @@
-use json;
 use std::fmt::{self, Display, Formatter};
 use std::io;
 use std::path::PathBuf;
+use std::time::Duration;
 
+#[derive(Debug)]
 pub enum Error {
     PathNotFound(PathBuf),
-    IO(io::Error),
-    JsonParsing(json::Error),
+    /// An operation on a file has failed, with the operation, such as
+    /// `"read"`, the file and the error
+    IO(&'static str, PathBuf, io::Error),
+    /// A json file could not be parsed, with the file and the error, which
+    /// has the line and column
+    JsonParsing(PathBuf, serde_json::Error),
+    TomlParsing(PathBuf, toml::de::Error),
+    YamlParsing(PathBuf, serde_yaml::Error),
     WrongConfigFormat(String),
-    NoEntry,
+    /// The config has been parsed but has a value latexbuild cannot build
+    /// with, with the reason
+    InvalidConfig(String),
+    /// A config could not be serialized, with the error of the serializer
+    ConfigSerialization(Box<dyn std::error::Error + Send + Sync>),
+    /// A file latexbuild writes, such as the build state or the SARIF
+    /// report, could not be serialized, with the file and the error
+    Serialization(PathBuf, serde_json::Error),
+    /// The entry file does not exist, with its path
+    NoEntry(PathBuf),
     Encoding,
+    /// A target that is not in the config was asked for
+    UnknownTarget(String),
+    /// A profile that is not in the config was asked for
+    UnknownProfile(String),
+    /// The program of the engine could not be found
+    EngineNotFound(String),
+    /// A program has exited unsuccessfully without reporting errors in its
+    /// log, with the program and its exit code, `None` if it was killed by a
+    /// signal
+    EngineFailed(String, Option<i32>),
+    /// A latex pass has taken longer than the timeout of the project and has
+    /// been killed, with the program and the timeout
+    Timeout(String, Duration),
+    /// The build has taken the maximum number of passes, with the aux
+    /// entries that changed during the last pass
+    TooManyPasses(usize, Vec<String>),
+    /// The aux file has returned to a previous state, with the aux entries
+    /// that keep changing
+    AuxOscillation(Vec<String>),
+    /// A directory of the includes could not be walked, with the directory
+    /// and the error, such as a permission error or an invalid ignore file
+    Walk(PathBuf, ignore::Error),
+}
+
+/// Write the aux entries that keep changing, one per line
+fn write_entries(f: &mut Formatter, entries: &[String]) -> Result<(), fmt::Error> {
+    if entries.is_empty() {
+        return Ok(());
+    }
+
+    write!(f, ", entries that keep changing:")?;
+
+    for entry in entries {
+        write!(f, "\n    {}", entry)?;
+    }
+
+    return Ok(());
 }
 
 impl Display for Error {
     fn fmt(&self, f: &mut Formatter) -> Result<(), self::fmt::Error> {
         match self {
             Error::PathNotFound(path_buf) => {
-                let s = path_buf.to_str();
-
-                match s {
-                    Some(s) => {
-                        write!(f, "{} not found", s)?;
-                    }
-                    None => {
-                        write!(f, "? not found")?;
-                    }
-                }
+                write!(f, "{} not found", path_buf.display())?;
+            }
+            Error::IO(operation, path_buf, error) => {
+                write!(
+                    f,
+                    "could not {} {}: {}",
+                    operation,
+                    path_buf.display(),
+                    error
+                )?;
             }
-            Error::IO(_error) => {
-                write!(f, "io error")?;
+            Error::JsonParsing(path_buf, error) => {
+                write!(f, "invalid {}: {}", path_buf.display(), error)?;
             }
-            Error::JsonParsing(_error) => {
-                write!(f, "json syntax error")?;
+            Error::TomlParsing(path_buf, error) => {
+                write!(f, "invalid {}: {}", path_buf.display(), error)?;
+            }
+            Error::YamlParsing(path_buf, error) => {
+                write!(f, "invalid {}: {}", path_buf.display(), error)?;
             }
             Error::Encoding => {
-                write!(f, "encoding error")?;
+                write!(f, "path or file is not valid utf-8")?;
             }
             Error::WrongConfigFormat(message) => {
                 write!(f, "{}", message)?;
             }
-            Error::NoEntry => {
-                write!(f, "no entry specified")?;
+            Error::InvalidConfig(message) => {
+                write!(f, "invalid config: {}", message)?;
+            }
+            Error::ConfigSerialization(error) => {
+                write!(f, "could not serialize the config: {}", error)?;
+            }
+            Error::Serialization(path, error) => {
+                write!(f, "could not serialize {}: {}", path.display(), error)?;
+            }
+            Error::NoEntry(path_buf) => {
+                write!(
+                    f,
+                    "entry file {} not found, create it or set `entry` in the config",
+                    path_buf.display()
+                )?;
+            }
+            Error::UnknownTarget(name) => {
+                write!(f, "no target named {}", name)?;
+            }
+            Error::UnknownProfile(name) => {
+                write!(f, "no profile named {}", name)?;
+            }
+            Error::EngineNotFound(program) => {
+                write!(f, "{} not found, is it installed and in PATH?", program)?;
+            }
+            Error::EngineFailed(program, Some(code)) => {
+                write!(
+                    f,
+                    "{} has exited with code {} without reporting an error, run with -vv to see its output",
+                    program, code
+                )?;
+            }
+            Error::EngineFailed(program, None) => {
+                write!(f, "{} has been killed by a signal", program)?;
+            }
+            Error::Timeout(program, timeout) => {
+                write!(
+                    f,
+                    "{} has been killed after running for {:?}",
+                    program, timeout
+                )?;
+            }
+            Error::TooManyPasses(passes, entries) => {
+                write!(f, "aux file has not stabilised after {} passes", passes)?;
+                write_entries(f, entries)?;
+            }
+            Error::AuxOscillation(entries) => {
+                write!(f, "aux file keeps changing between the same states")?;
+                write_entries(f, entries)?;
+            }
+            Error::Walk(path, error) => {
+                write!(f, "could not walk {}: {}", path.display(), error)?;
             }
         }
 
         return Ok(());
     }
 }
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        return match self {
+            Error::IO(_, _, error) => Some(error),
+            Error::JsonParsing(_, error) => Some(error),
+            Error::TomlParsing(_, error) => Some(error),
+            Error::YamlParsing(_, error) => Some(error),
+            Error::Walk(_, error) => Some(error),
+            Error::ConfigSerialization(error) => Some(error.as_ref()),
+            Error::Serialization(_, error) => Some(error),
+            _ => None,
+        };
+    }
+}
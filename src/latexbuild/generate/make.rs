@@ -2,7 +2,7 @@ use super::super::Project;
 use super::Error;
 use super::Generate;
 use std::fs::File;
-use std::io::{BufWriter, Write};
+use std::io::{self, BufWriter, Write};
 
 pub enum MakeDependency {
     Regular(String),
@@ -10,25 +10,17 @@ pub enum MakeDependency {
 }
 
 impl Generate for MakeDependency {
-    fn generate(&self, writer: &mut BufWriter<File>) -> Result<(), Error> {
-        match self {
-            MakeDependency::Regular(ref d) => match write!(writer, "{}", d) {
-                Err(error) => return Err(Error::IO(error)),
-                _ => {}
-            },
-            MakeDependency::OrderOnly(ref d) => match write!(writer, "| {}", d) {
-                Err(error) => return Err(Error::IO(error)),
-                _ => {}
-            },
-        }
-
-        return Ok(());
+    fn generate(&self, writer: &mut BufWriter<File>) -> io::Result<()> {
+        return match self {
+            MakeDependency::Regular(ref d) => write!(writer, "{}", d),
+            MakeDependency::OrderOnly(ref d) => write!(writer, "| {}", d),
+        };
     }
 }
 
 pub struct MakeTarget {
     target: String,
-    command: String,
+    commands: Vec<String>,
     dependencies: Vec<MakeDependency>,
 }
 
@@ -45,12 +37,9 @@ impl Makefile {
 }
 
 impl Generate for Makefile {
-    fn generate(&self, writer: &mut BufWriter<File>) -> Result<(), Error> {
+    fn generate(&self, writer: &mut BufWriter<File>) -> io::Result<()> {
         for target in &self.targets {
-            match write!(writer, "{}: ", target.target) {
-                Err(error) => return Err(Error::IO(error)),
-                _ => {}
-            }
+            write!(writer, "{}: ", target.target)?;
 
             let mut dep_iter = target.dependencies.iter();
 
@@ -62,27 +51,14 @@ impl Generate for Makefile {
             }
 
             for dep in dep_iter {
-                match write!(writer, " ") {
-                    Err(error) => return Err(Error::IO(error)),
-                    _ => {}
-                }
-
+                write!(writer, " ")?;
                 dep.generate(writer)?;
             }
 
-            match writeln!(writer) {
-                Err(error) => return Err(Error::IO(error)),
-                _ => {}
-            }
-
-            match write!(writer, "\t") {
-                Err(error) => return Err(Error::IO(error)),
-                _ => {}
-            }
+            writeln!(writer)?;
 
-            match writeln!(writer, "{}", target.command) {
-                Err(error) => return Err(Error::IO(error)),
-                _ => {}
+            for command in &target.commands {
+                writeln!(writer, "\t{}", command)?;
             }
         }
 
@@ -90,11 +66,26 @@ impl Generate for Makefile {
     }
 }
 
+/// Quote an argument for the shell running the recipes, if it has characters
+/// the shell would interpret
+fn shell_quote(arg: &str) -> String {
+    let is_plain = arg
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || "-_=./,:+".contains(c));
+
+    if is_plain {
+        return String::from(arg);
+    }
+
+    return format!("'{}'", arg.replace('\'', "'\\''"));
+}
+
 impl Project {
-    pub fn to_make(&self) -> Result<Makefile, Error> {
+    /// The rule building the pdf of the project
+    fn to_make_target(&self) -> Result<MakeTarget, Error> {
         let mut dependencies: Vec<MakeDependency> = Vec::new();
 
-        for file in self.files() {
+        for file in &self.dependencies() {
             match file.to_str() {
                 Some(file) => {
                     dependencies.push(MakeDependency::Regular(String::from(file)));
@@ -103,15 +94,6 @@ impl Project {
             }
         }
 
-        let project = match self.bin().to_str() {
-            Some(project) => project,
-            _ => return Err(Error::Encoding),
-        };
-
-        dependencies.push(MakeDependency::OrderOnly(String::from(project)));
-
-        let mut makefile = Makefile::new();
-
         let latex = match self.latex().to_str() {
             Some(latex) => latex,
             _ => return Err(Error::Encoding),
@@ -122,28 +104,125 @@ impl Project {
             _ => return Err(Error::Encoding),
         };
 
+        dependencies.push(MakeDependency::OrderOnly(String::from(bin)));
+
         let pdf = match self.pdf().to_str() {
             Some(pdf) => pdf,
             _ => return Err(Error::Encoding),
         };
 
-        let entry = match self.entry().to_str() {
-            Some(entry) => entry,
-            _ => return Err(Error::Encoding),
-        };
+        let args: Vec<String> = self
+            .latex_args()?
+            .iter()
+            .map(|arg| shell_quote(arg))
+            .collect();
+
+        // make expands `$` in recipes before the shell sees them
+        let mut commands = vec![format!("{} {}", latex, args.join(" ")).replace('$', "$$")];
+
+        if let Some(converter) = self.engine().converter() {
+            let mut command = String::from(converter);
+
+            for arg in self.converter_args() {
+                match arg.to_str() {
+                    Some(arg) => {
+                        command.push(' ');
+                        command.push_str(&shell_quote(arg));
+                    }
+                    None => return Err(Error::Encoding),
+                }
+            }
+
+            commands.push(command.replace('$', "$$"));
+        }
 
-        makefile.targets.push(MakeTarget {
+        return Ok(MakeTarget {
             target: String::from(pdf),
-            command: format!("{} -output-directory={} {}", latex, bin, entry),
-            dependencies: dependencies,
+            commands,
+            dependencies,
         });
+    }
 
-        makefile.targets.push(MakeTarget {
-            target: String::from(bin),
-            command: format!("mkdir {}", bin),
-            dependencies: Vec::new(),
-        });
+    pub fn to_make(&self) -> Result<Makefile, Error> {
+        return Project::projects_to_make(std::slice::from_ref(self));
+    }
+
+    /// Create a makefile with one rule per project, and an `all` rule
+    /// building all of them when there are several
+    ///
+    /// # Arguments
+    ///
+    /// - `projects`: the projects, usually the targets of a config
+    pub fn projects_to_make(projects: &[Project]) -> Result<Makefile, Error> {
+        let mut makefile = Makefile::new();
+
+        if projects.len() > 1 {
+            let mut dependencies: Vec<MakeDependency> = Vec::new();
+
+            for project in projects {
+                match project.pdf().to_str() {
+                    Some(pdf) => dependencies.push(MakeDependency::Regular(String::from(pdf))),
+                    None => return Err(Error::Encoding),
+                }
+            }
+
+            makefile.targets.push(MakeTarget {
+                target: String::from("all"),
+                commands: Vec::new(),
+                dependencies,
+            });
+        }
+
+        let mut bins: Vec<String> = Vec::new();
+
+        for project in projects {
+            makefile.targets.push(project.to_make_target()?);
+
+            match project.bin().to_str() {
+                Some(bin) => {
+                    if !bins.iter().any(|other| other == bin) {
+                        bins.push(String::from(bin));
+                    }
+                }
+                None => return Err(Error::Encoding),
+            }
+        }
+
+        for bin in bins {
+            makefile.targets.push(MakeTarget {
+                target: bin.clone(),
+                commands: vec![format!("mkdir -p {}", bin)],
+                dependencies: Vec::new(),
+            });
+        }
 
         return Ok(makefile);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plain_arguments_are_not_quoted() {
+        assert_eq!(
+            shell_quote("-output-directory=./bin"),
+            "-output-directory=./bin"
+        );
+        assert_eq!(
+            shell_quote("-interaction=nonstopmode"),
+            "-interaction=nonstopmode"
+        );
+    }
+
+    #[test]
+    fn arguments_with_shell_characters_are_quoted() {
+        assert_eq!(
+            shell_quote("\\def\\draft{1}\\input{\"index.tex\"}"),
+            "'\\def\\draft{1}\\input{\"index.tex\"}'"
+        );
+        assert_eq!(shell_quote("my thesis.tex"), "'my thesis.tex'");
+        assert_eq!(shell_quote("it's.tex"), "'it'\\''s.tex'");
+    }
+}
@@ -1,11 +1,11 @@
 use super::Error;
 use std::fs::File;
-use std::io::BufWriter;
+use std::io::{self, BufWriter};
 
 mod make;
 
 pub use make::*;
 
 pub trait Generate {
-    fn generate(&self, writer: &mut BufWriter<File>) -> Result<(), Error>;
+    fn generate(&self, writer: &mut BufWriter<File>) -> io::Result<()>;
 }
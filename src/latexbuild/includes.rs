@@ -0,0 +1,293 @@
+use super::Error;
+use globset::{GlobBuilder, GlobMatcher};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::WalkBuilder;
+use std::path::{Component, Path, PathBuf};
+
+/// The name of the ignore files read in addition to `.gitignore` and `.ignore`
+const IGNORE_FILE_NAME: &str = ".latexbuildignore";
+
+/// Determine if an include is a glob pattern rather than a literal path
+fn is_pattern(include: &str) -> bool {
+    return include.contains(['*', '?', '[', '{']);
+}
+
+/// The directory to walk to find the files matching a pattern, made of the
+/// components of the pattern before the first one with a glob
+fn pattern_base(pattern: &str) -> PathBuf {
+    let mut base = PathBuf::new();
+
+    for component in pattern.split('/') {
+        if is_pattern(component) {
+            break;
+        }
+
+        base.push(component);
+    }
+
+    return base;
+}
+
+/// Remove the `.` components, so that paths found by walking `.` can be
+/// matched against patterns
+fn normalize(path: &Path) -> PathBuf {
+    return path
+        .components()
+        .filter(|component| *component != Component::CurDir)
+        .collect();
+}
+
+fn pattern_error(key: &str, error: impl std::fmt::Display) -> Error {
+    return Error::WrongConfigFormat(format!("invalid pattern in {}: {}", key, error));
+}
+
+fn build_include(pattern: &str) -> Result<GlobMatcher, Error> {
+    // `*` does not match `/`, so that `chapters/*.tex` does not match the
+    // files of the subdirectories of `chapters`, `**` does
+    return match GlobBuilder::new(pattern).literal_separator(true).build() {
+        Ok(glob) => Ok(glob.compile_matcher()),
+        Err(error) => Err(pattern_error("includes", error)),
+    };
+}
+
+fn build_excludes(root: &Path, excludes: &[String]) -> Result<Gitignore, Error> {
+    let mut builder = GitignoreBuilder::new(root);
+
+    for exclude in excludes {
+        if let Err(error) = builder.add_line(None, exclude) {
+            return Err(pattern_error("excludes", error));
+        }
+    }
+
+    return match builder.build() {
+        Ok(gitignore) => Ok(gitignore),
+        Err(error) => Err(pattern_error("excludes", error)),
+    };
+}
+
+/// Walk a directory, skipping hidden files, the files ignored by ignore files
+/// and the excluded files
+///
+/// # Arguments
+///
+/// - `root`: the root of the project
+/// - `dir`: the directory to walk, relative to `root`
+/// - `excludes`: the excluded files
+///
+/// # Returns
+///
+/// The files found, relative to `root`
+fn walk(root: &Path, dir: &Path, excludes: &Gitignore) -> Result<Vec<PathBuf>, Error> {
+    let mut files: Vec<PathBuf> = Vec::new();
+    let filter_root = PathBuf::from(root);
+    let filter_excludes = excludes.clone();
+
+    let walker = WalkBuilder::new(root.join(dir))
+        // projects are not always git repositories, `.gitignore` is read
+        // regardless
+        .require_git(false)
+        .add_custom_ignore_filename(IGNORE_FILE_NAME)
+        .sort_by_file_name(|a, b| a.cmp(b))
+        .filter_entry(move |entry| {
+            let path = match entry.path().strip_prefix(&filter_root) {
+                Ok(path) => normalize(path),
+                Err(_) => return true,
+            };
+
+            let is_dir = entry.file_type().map(|file_type| file_type.is_dir()) == Some(true);
+
+            return !filter_excludes.matched(&path, is_dir).is_ignore();
+        })
+        .build();
+
+    for entry in walker {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(error) => return Err(Error::Walk(root.join(dir), error)),
+        };
+
+        if entry.file_type().map(|file_type| file_type.is_file()) != Some(true) {
+            continue;
+        }
+
+        match entry.path().strip_prefix(root) {
+            Ok(path) => files.push(normalize(path)),
+            Err(_) => {}
+        }
+    }
+
+    return Ok(files);
+}
+
+/// Resolve the `includes` of a config into the files they refer to
+///
+/// # Arguments
+///
+/// - `root`: the directory of the config, which the includes are relative to
+/// - `includes`: literal files, directories, which are walked recursively,
+///   and glob patterns such as `chapters/**/*.tex` or `figures/*.{pdf,png}`
+/// - `excludes`: `.gitignore`-style patterns of the files not to include
+///   from directories and glob patterns
+///
+/// # Returns
+///
+/// The files, relative to `root`. Literal files are returned even if they
+/// do not exist, so that a missing file is reported when building
+pub(crate) fn resolve_includes(
+    root: &Path,
+    includes: &[PathBuf],
+    excludes: &[String],
+) -> Result<Vec<PathBuf>, Error> {
+    let excludes = build_excludes(root, excludes)?;
+    let mut files: Vec<PathBuf> = Vec::new();
+
+    for include in includes {
+        let include_str = match include.to_str() {
+            Some(include_str) => include_str,
+            None => return Err(Error::Encoding),
+        };
+
+        let found = if is_pattern(include_str) {
+            // the walked files have no `.` components to match against
+            let include_str = include_str.trim_start_matches("./");
+            let base = pattern_base(include_str);
+
+            if !root.join(&base).is_dir() {
+                continue;
+            }
+
+            let glob = build_include(include_str)?;
+
+            walk(root, &base, &excludes)?
+                .into_iter()
+                .filter(|file| glob.is_match(file))
+                .collect()
+        } else if root.join(include).is_dir() {
+            walk(root, include, &excludes)?
+        } else {
+            vec![normalize(include)]
+        };
+
+        for file in found {
+            if !files.contains(&file) {
+                files.push(file);
+            }
+        }
+    }
+
+    return Ok(files);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::{create_dir_all, write};
+
+    /// A project with chapters, a subdirectory of chapters and figures
+    fn project() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+
+        for file in &[
+            "chapters/intro.tex",
+            "chapters/method.tex",
+            "chapters/appendix/proofs.tex",
+            "chapters/notes.txt",
+            "figures/plot.pdf",
+            "figures/plot.png",
+            "figures/.hidden.pdf",
+        ] {
+            let path = dir.path().join(file);
+            create_dir_all(path.parent().unwrap()).unwrap();
+            write(&path, "").unwrap();
+        }
+
+        return dir;
+    }
+
+    fn resolve(root: &Path, includes: &[&str], excludes: &[&str]) -> Vec<PathBuf> {
+        let includes: Vec<PathBuf> = includes.iter().map(PathBuf::from).collect();
+        let excludes: Vec<String> = excludes.iter().map(|e| String::from(*e)).collect();
+
+        return resolve_includes(root, &includes, &excludes).unwrap();
+    }
+
+    fn paths(paths: &[&str]) -> Vec<PathBuf> {
+        return paths.iter().map(PathBuf::from).collect();
+    }
+
+    #[test]
+    fn star_does_not_cross_directories() {
+        let dir = project();
+
+        assert_eq!(
+            resolve(dir.path(), &["chapters/*.tex"], &[]),
+            paths(&["chapters/intro.tex", "chapters/method.tex"])
+        );
+    }
+
+    #[test]
+    fn double_star_crosses_directories() {
+        let dir = project();
+
+        assert_eq!(
+            resolve(dir.path(), &["./chapters/**/*.tex"], &[]),
+            paths(&[
+                "chapters/appendix/proofs.tex",
+                "chapters/intro.tex",
+                "chapters/method.tex",
+            ])
+        );
+    }
+
+    #[test]
+    fn directories_are_walked_without_hidden_and_excluded_files() {
+        let dir = project();
+
+        assert_eq!(
+            resolve(dir.path(), &["figures"], &["*.png"]),
+            paths(&["figures/plot.pdf"])
+        );
+    }
+
+    #[test]
+    fn ignore_file_is_read() {
+        let dir = project();
+        write(dir.path().join(IGNORE_FILE_NAME), "appendix/\n*.txt\n").unwrap();
+
+        assert_eq!(
+            resolve(dir.path(), &["chapters"], &[]),
+            paths(&["chapters/intro.tex", "chapters/method.tex"])
+        );
+    }
+
+    #[test]
+    fn literal_files_are_kept_once() {
+        let dir = project();
+
+        assert_eq!(
+            resolve(
+                dir.path(),
+                &["missing.tex", "chapters/intro.tex", "chapters/*.tex"],
+                &[]
+            ),
+            paths(&["missing.tex", "chapters/intro.tex", "chapters/method.tex"])
+        );
+    }
+
+    #[test]
+    fn pattern_without_directory_matches_nothing() {
+        let dir = project();
+
+        assert!(resolve(dir.path(), &["slides/*.tex"], &[]).is_empty());
+    }
+
+    #[test]
+    fn invalid_pattern_is_a_config_error() {
+        let dir = project();
+
+        assert!(matches!(
+            resolve_includes(dir.path(), &[PathBuf::from("chapters/{a.tex")], &[]),
+            Err(Error::WrongConfigFormat(_))
+        ));
+    }
+}
@@ -0,0 +1,276 @@
+use super::Error;
+use super::{absolute, BuildOutcome, Logger, Project, StepOutcome, Verbosity};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fs::read;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Instant;
+
+/// The program used to process `.idx` files
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Index {
+    Makeindex,
+    Xindy,
+}
+
+impl Index {
+    pub fn name(&self) -> &'static str {
+        return match self {
+            Index::Makeindex => "makeindex",
+            Index::Xindy => "xindy",
+        };
+    }
+}
+
+/// The extensions of the files written by the glossaries package, and the
+/// extensions of the files makeglossaries generates from them
+const GLOSSARY_EXTENSIONS: [(&str, &str); 2] = [("glo", "gls"), ("acn", "acr")];
+
+impl Project {
+    /// The `.idx` file written by the last latex pass, `None` if there is
+    /// none. The targets share the bin directory, so only the file of the
+    /// job is processed
+    pub fn idx_file(&self) -> Option<PathBuf> {
+        let idx = self.aux().with_extension("idx");
+
+        if !idx.exists() {
+            return None;
+        }
+
+        return Some(idx);
+    }
+
+    /// The glossary files written by the last latex pass, paired with the
+    /// files makeglossaries generates from them
+    pub fn glossary_files(&self) -> Vec<(PathBuf, PathBuf)> {
+        let mut files: Vec<(PathBuf, PathBuf)> = Vec::new();
+
+        for (input, output) in &GLOSSARY_EXTENSIONS {
+            let input = self.aux().with_extension(input);
+
+            if input.exists() {
+                let output = input.with_extension(output);
+                files.push((input, output));
+            }
+        }
+
+        return files;
+    }
+
+    /// Run makeindex or xindy on an `.idx` file inside the bin directory
+    ///
+    /// # Arguments
+    ///
+    /// - `idx`: the `.idx` file
+    /// - `logger`: the logger
+    ///
+    /// # Returns
+    ///
+    /// `true` if the program exited successfully
+    pub fn build_index<L: Logger>(&self, idx: &Path, logger: &mut L) -> Result<bool, Error> {
+        let mut args: Vec<OsString> = Vec::new();
+
+        match self.index() {
+            Index::Makeindex => {
+                if let Some(style) = self.index_style() {
+                    args.push(OsString::from("-s"));
+                    args.push(absolute(style).into_os_string());
+                }
+            }
+            Index::Xindy => {
+                args.push(OsString::from("-M"));
+                args.push(OsString::from("texindy"));
+
+                if let Some(style) = self.index_style() {
+                    args.push(OsString::from("-M"));
+                    args.push(absolute(style).into_os_string());
+                }
+            }
+        }
+
+        match idx.file_name() {
+            Some(file_name) => args.push(OsString::from(file_name)),
+            None => return Err(Error::Encoding),
+        }
+
+        return self.run_in_bin(self.index().name(), &args, logger);
+    }
+
+    /// Run makeglossaries inside the bin directory
+    ///
+    /// # Returns
+    ///
+    /// `true` if the program exited successfully
+    pub fn build_glossaries<L: Logger>(&self, logger: &mut L) -> Result<bool, Error> {
+        let mut args: Vec<OsString> = Vec::new();
+
+        if let Some(style) = self.glossary_style() {
+            args.push(OsString::from("-s"));
+            args.push(absolute(style).into_os_string());
+        }
+
+        match self.aux().file_stem() {
+            Some(job) => args.push(OsString::from(job)),
+            None => return Err(Error::Encoding),
+        }
+
+        return self.run_in_bin("makeglossaries", &args, logger);
+    }
+
+    fn run_in_bin<L: Logger>(
+        &self,
+        program: &str,
+        args: &[OsString],
+        logger: &mut L,
+    ) -> Result<bool, Error> {
+        logger.run_command(program, args);
+
+        let started = Instant::now();
+
+        let command_output = match Command::new(program)
+            .args(args)
+            .current_dir(self.bin())
+            .output()
+        {
+            Ok(output) => output,
+            Err(error) if error.kind() == ErrorKind::NotFound => {
+                return Err(Error::EngineNotFound(String::from(program)));
+            }
+            Err(error) => return Err(Error::IO("run", PathBuf::from(program), error)),
+        };
+
+        logger.command_finished(program, command_output.status.code(), started.elapsed());
+
+        if logger.verbosity() >= Verbosity::VeryVerbose {
+            logger.command_output(String::from_utf8_lossy(&command_output.stdout));
+        }
+
+        return Ok(command_output.status.success());
+    }
+}
+
+/// An object that determines if makeindex, xindy or makeglossaries needs to
+/// run after a latex pass, and runs them
+///
+/// # Discussion
+///
+/// - `build()` should be called after every latex pass
+pub struct IndexBuilder<'a> {
+    pub project: &'a Project,
+    old_inputs: HashMap<PathBuf, Vec<u8>>,
+}
+
+impl<'a> IndexBuilder<'a> {
+    /// Create an index builder using a project, the inputs are those of the
+    /// last build, before any latex pass is run
+    pub fn new(project: &'a Project) -> IndexBuilder<'a> {
+        IndexBuilder {
+            project,
+            old_inputs: IndexBuilder::read_inputs(project),
+        }
+    }
+
+    fn read_inputs(project: &Project) -> HashMap<PathBuf, Vec<u8>> {
+        let mut inputs: HashMap<PathBuf, Vec<u8>> = HashMap::new();
+        let glossary_files = project.glossary_files();
+
+        let files = project
+            .idx_file()
+            .into_iter()
+            .chain(glossary_files.into_iter().map(|(input, _output)| input));
+
+        for file in files {
+            if let Ok(content) = read(&file) {
+                inputs.insert(file, content);
+            }
+        }
+
+        return inputs;
+    }
+
+    /// An input needs processing if its output does not exist or it has
+    /// changed since the last pass
+    fn needs_build(&self, inputs: &HashMap<PathBuf, Vec<u8>>, input: &Path, output: &Path) -> bool {
+        return !output.exists() || inputs.get(input) != self.old_inputs.get(input);
+    }
+
+    /// Run makeindex or xindy for the `.idx` file, and makeglossaries for
+    /// the glossaries, if their outputs do not exist yet or the inputs have
+    /// changed since the last pass
+    ///
+    /// # Returns
+    ///
+    /// `StepOutcome::Changed` if a generated `.ind`, `.gls` or `.acr` file
+    /// has changed, in which case latex needs another pass, and
+    /// `StepOutcome::Failed` if a program is not installed or has failed
+    pub fn build<L: Logger>(&mut self, logger: &mut L) -> Result<StepOutcome, Error> {
+        let inputs = IndexBuilder::read_inputs(self.project);
+        let mut changed = false;
+
+        if let Some(idx) = self.project.idx_file() {
+            let ind = idx.with_extension("ind");
+
+            if self.needs_build(&inputs, &idx, &ind) {
+                let old_ind = read(&ind).ok();
+
+                logger.message("building index");
+
+                match self.project.build_index(&idx, logger) {
+                    Ok(true) => {}
+                    Ok(false) => {
+                        logger.error(format!(
+                            "{} stopped due to error",
+                            self.project.index().name()
+                        ));
+                        return Ok(StepOutcome::Failed(BuildOutcome::Failed));
+                    }
+                    Err(error) => return StepOutcome::from_error(error, logger),
+                }
+
+                changed = read(&ind).ok() != old_ind;
+            }
+        }
+
+        let glossary_files = self.project.glossary_files();
+
+        if glossary_files
+            .iter()
+            .any(|(input, output)| self.needs_build(&inputs, input, output))
+        {
+            let old_outputs: Vec<Option<Vec<u8>>> = glossary_files
+                .iter()
+                .map(|(_input, output)| read(output).ok())
+                .collect();
+
+            logger.message("building glossaries");
+
+            match self.project.build_glossaries(logger) {
+                Ok(true) => {}
+                Ok(false) => {
+                    logger.error("makeglossaries stopped due to error");
+                    return Ok(StepOutcome::Failed(BuildOutcome::Failed));
+                }
+                Err(error) => return StepOutcome::from_error(error, logger),
+            }
+
+            let new_outputs: Vec<Option<Vec<u8>>> = glossary_files
+                .iter()
+                .map(|(_input, output)| read(output).ok())
+                .collect();
+
+            changed = changed || new_outputs != old_outputs;
+        }
+
+        self.old_inputs = inputs;
+
+        if changed {
+            return Ok(StepOutcome::Changed);
+        }
+
+        return Ok(StepOutcome::Unchanged);
+    }
+}
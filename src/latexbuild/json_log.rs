@@ -0,0 +1,295 @@
+use super::{BuildOutcome, Diagnostic, DiagnosticKind, Logger, RebuildReason, Verbosity};
+use serde_json::{json, Map, Value};
+use std::ffi::OsStr;
+use std::io::{stdout, Stdout, Write};
+use std::time::Duration;
+
+/// `trait Logger` implementation writing one JSON object per line to standard
+/// output, for editors and continuous integration. Every object has an
+/// `event` key naming the event. The events are filtered by verbosity in the
+/// same way as with `StdErrLogger`, except for `error` and `build_finished`
+pub struct JsonLogger<W: Write = Stdout> {
+    verbosity: Verbosity,
+    output: W,
+}
+
+impl JsonLogger {
+    pub fn new() -> JsonLogger {
+        return JsonLogger::with_verbosity(Verbosity::Normal);
+    }
+
+    pub fn with_verbosity(verbosity: Verbosity) -> JsonLogger {
+        return JsonLogger::with_output(verbosity, stdout());
+    }
+}
+
+impl<W: Write> JsonLogger<W> {
+    /// Create a logger writing the events to `output` rather than to
+    /// standard output
+    ///
+    /// # Arguments
+    ///
+    /// - `verbosity`: the verbosity
+    /// - `output`: where the events are written
+    pub fn with_output(verbosity: Verbosity, output: W) -> JsonLogger<W> {
+        JsonLogger { verbosity, output }
+    }
+
+    fn write(&mut self, event: Value) {
+        // a closed stdout is not worth stopping the build for
+        let _ = writeln!(self.output, "{}", event);
+        let _ = self.output.flush();
+    }
+}
+
+impl<W: Write> Logger for JsonLogger<W> {
+    fn verbosity(&self) -> Verbosity {
+        return self.verbosity;
+    }
+
+    fn run_command<CS, I, S>(&mut self, command: CS, args: I)
+    where
+        CS: AsRef<OsStr>,
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        if self.verbosity < Verbosity::Verbose {
+            return;
+        }
+
+        let args: Vec<String> = args
+            .into_iter()
+            .map(|arg| arg.as_ref().to_string_lossy().into_owned())
+            .collect();
+
+        self.write(json!({
+            "event": "command_started",
+            "command": command.as_ref().to_string_lossy(),
+            "args": args,
+        }));
+    }
+
+    fn command_output<S>(&mut self, s: S)
+    where
+        S: AsRef<str>,
+    {
+        self.write(json!({
+            "event": "command_output",
+            "output": s.as_ref(),
+        }));
+    }
+
+    fn command_error_output<S>(&mut self, s: S)
+    where
+        S: AsRef<str>,
+    {
+        self.write(json!({
+            "event": "command_error_output",
+            "output": s.as_ref(),
+        }));
+    }
+
+    fn error<S>(&mut self, error: S)
+    where
+        S: AsRef<str>,
+    {
+        self.write(json!({
+            "event": "error",
+            "message": error.as_ref(),
+        }));
+    }
+
+    fn message<S>(&mut self, message: S)
+    where
+        S: AsRef<str>,
+    {
+        if self.verbosity < Verbosity::Normal {
+            return;
+        }
+
+        self.write(json!({
+            "event": "message",
+            "message": message.as_ref(),
+        }));
+    }
+
+    fn diagnostic(&mut self, diagnostic: &Diagnostic) {
+        if !diagnostic.is_error() && self.verbosity < Verbosity::Normal {
+            return;
+        }
+
+        let mut event = Map::new();
+
+        let severity = if diagnostic.is_error() {
+            "error"
+        } else {
+            "warning"
+        };
+
+        event.insert(String::from("event"), json!("diagnostic"));
+        event.insert(String::from("severity"), json!(severity));
+        event.insert(String::from("kind"), json!(diagnostic.kind.name()));
+
+        match &diagnostic.kind {
+            DiagnosticKind::UndefinedReference(label) => {
+                event.insert(String::from("label"), json!(label));
+            }
+            DiagnosticKind::UndefinedCitation(key) => {
+                event.insert(String::from("key"), json!(key));
+            }
+            DiagnosticKind::PackageWarning(package) => {
+                event.insert(String::from("package"), json!(package));
+            }
+            _ => {}
+        }
+
+        event.insert(String::from("file"), json!(diagnostic.file));
+        event.insert(String::from("line"), json!(diagnostic.line));
+        event.insert(String::from("message"), json!(diagnostic.message));
+
+        self.write(Value::Object(event));
+    }
+
+    fn pass_started(&mut self, target: Option<&str>, pass: usize, reason: &RebuildReason) {
+        if self.verbosity < Verbosity::Normal {
+            return;
+        }
+
+        self.write(json!({
+            "event": "pass_started",
+            "target": target,
+            "pass": pass,
+            "reason": reason.name(),
+            "message": format!("{}", reason),
+        }));
+    }
+
+    fn command_finished<CS>(&mut self, command: CS, status: Option<i32>, duration: Duration)
+    where
+        CS: AsRef<OsStr>,
+    {
+        if self.verbosity < Verbosity::Verbose {
+            return;
+        }
+
+        self.write(json!({
+            "event": "command_finished",
+            "command": command.as_ref().to_string_lossy(),
+            "status": status,
+            "duration": duration.as_secs_f64(),
+        }));
+    }
+
+    fn build_finished(&mut self, target: Option<&str>, outcome: BuildOutcome, passes: usize) {
+        self.write(json!({
+            "event": "build_finished",
+            "target": target,
+            "outcome": outcome.name(),
+            "success": outcome <= BuildOutcome::Built,
+            "passes": passes,
+        }));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    /// The events written by a logger, one JSON object per line
+    fn events(logger: JsonLogger<Vec<u8>>) -> Vec<Value> {
+        return String::from_utf8(logger.output)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+    }
+
+    fn warning() -> Diagnostic {
+        return Diagnostic {
+            kind: DiagnosticKind::UndefinedReference(String::from("sec:intro")),
+            file: Some(PathBuf::from("index.tex")),
+            line: Some(12),
+            message: String::from("Reference `sec:intro' on page 1 undefined"),
+        };
+    }
+
+    #[test]
+    fn events_are_json_lines() {
+        let mut logger = JsonLogger::with_output(Verbosity::Normal, Vec::new());
+        logger.pass_started(Some("paper"), 1, &RebuildReason::PdfMissing);
+        logger.diagnostic(&warning());
+        logger.build_finished(Some("paper"), BuildOutcome::TimedOut, 1);
+
+        assert_eq!(
+            events(logger),
+            vec![
+                json!({
+                    "event": "pass_started",
+                    "target": "paper",
+                    "pass": 1,
+                    "reason": "pdf-missing",
+                    "message": "pdf does not exist",
+                }),
+                json!({
+                    "event": "diagnostic",
+                    "severity": "warning",
+                    "kind": "undefined-reference",
+                    "label": "sec:intro",
+                    "file": "index.tex",
+                    "line": 12,
+                    "message": "Reference `sec:intro' on page 1 undefined",
+                }),
+                json!({
+                    "event": "build_finished",
+                    "target": "paper",
+                    "outcome": "timed-out",
+                    "success": false,
+                    "passes": 1,
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn quiet_logger_only_writes_errors_and_results() {
+        let mut logger = JsonLogger::with_output(Verbosity::Quiet, Vec::new());
+        logger.run_command("pdflatex", ["index.tex"]);
+        logger.message("building bibliography with bibtex");
+        logger.pass_started(None, 1, &RebuildReason::AuxChanged);
+        logger.diagnostic(&warning());
+        logger.command_finished("pdflatex", Some(0), Duration::from_secs(1));
+        logger.error("build stopped due to error");
+        logger.build_finished(None, BuildOutcome::Failed, 1);
+
+        let events = events(logger);
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0]["event"], "error");
+        assert_eq!(events[1]["event"], "build_finished");
+        assert_eq!(events[1]["outcome"], "failed");
+    }
+
+    #[test]
+    fn verbose_logger_writes_commands() {
+        let mut logger = JsonLogger::with_output(Verbosity::Verbose, Vec::new());
+        logger.run_command("pdflatex", ["index.tex"]);
+        logger.command_finished("pdflatex", None, Duration::from_millis(1500));
+
+        assert_eq!(
+            events(logger),
+            vec![
+                json!({
+                    "event": "command_started",
+                    "command": "pdflatex",
+                    "args": ["index.tex"],
+                }),
+                json!({
+                    "event": "command_finished",
+                    "command": "pdflatex",
+                    "status": null,
+                    "duration": 1.5,
+                }),
+            ]
+        );
+    }
+}
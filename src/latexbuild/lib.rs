@@ -4,22 +4,57 @@ pub use error::*;
 mod log;
 pub use log::*;
 
+mod json_log;
+pub use json_log::*;
+
 mod build_check;
 pub use build_check::*;
 
+mod config;
+pub use config::*;
+
+mod engine;
+pub use engine::*;
+
+mod includes;
+use includes::*;
+
 mod project;
 pub use project::*;
 
+mod log_parser;
+pub use log_parser::*;
+
 mod build;
 pub use build::*;
 
+mod bibliography;
+pub use bibliography::*;
+
+mod index;
+pub use index::*;
+
+mod recorder;
+
+mod state;
+pub use state::*;
+
+mod sarif;
+pub use sarif::*;
+
+mod watch;
+
 mod generate;
 use generate::*;
 
 use std::fs::{remove_dir_all, File};
-use std::io::BufWriter;
+use std::io::{BufWriter, ErrorKind, Write};
 use std::path::PathBuf;
 
+/// The name of a target, `None` if the config has no targets, with the reason
+/// it needs a build, `None` if it is up to date
+pub type TargetRebuildReason = (Option<String>, Option<RebuildReason>);
+
 /// Wrapper for the build pipeline
 pub struct LatexBuild<'a, L>
 where
@@ -27,6 +62,10 @@ where
 {
     /// Path to the config
     pub config_path: PathBuf,
+    /// The target to build, all the targets of the config if `None`
+    pub target: Option<String>,
+    /// The profile to build with, if any
+    pub profile: Option<String>,
     /// A mutable reference to the logger
     pub logger: &'a mut L,
 }
@@ -35,78 +74,301 @@ impl<'a, L> LatexBuild<'a, L>
 where
     L: Logger,
 {
-    /// Load a project and call `use_root_path` on it. In another word,
-    /// load a project and make all the paths absolute
-    pub fn load_project(&self) -> Result<Project, Error> {
+    /// The directory of the config, which the paths of the projects are
+    /// relative to
+    pub fn root_path(&self) -> PathBuf {
         let mut root_path = self.config_path.clone();
         root_path.pop();
 
-        let mut project = Project::load(&self.config_path)?;
-        project.use_root_path(&root_path);
+        return root_path;
+    }
+
+    /// Load the project of `target`, or the first project, and call
+    /// `use_root_path` on it, see `load_projects`
+    pub fn load_project(&self) -> Result<Project, Error> {
+        let mut projects = self.load_projects()?;
 
-        return Ok(project);
+        return Ok(projects.remove(0));
     }
 
-    /// Run the build pipeline
-    pub fn build(&mut self) -> Result<(), Error> {
-        let project = self.load_project()?;
+    /// Load the projects of the targets and call `use_root_path` on them. In
+    /// another word, load the projects and make all the paths absolute
+    pub fn load_projects(&self) -> Result<Vec<Project>, Error> {
+        let root_path = self.root_path();
+
+        let mut projects = Project::load_targets(
+            &self.config_path,
+            self.target.as_deref(),
+            self.profile.as_deref(),
+        )?;
+
+        for project in &mut projects {
+            project.use_root_path(&root_path);
+        }
+
+        return Ok(projects);
+    }
 
-        match project.can_build() {
-            Err(error) => match error {
-                Error::NoEntry => {
-                    self.logger.error("no entry file");
-                    return Ok(());
+    /// Run the build pipeline on every target
+    ///
+    /// # Returns
+    ///
+    /// The outcome of the target that has fared the worst, a target that
+    /// fails, has no entry, or whose engine is missing or times out is an
+    /// outcome rather than an error, so that the other targets are still
+    /// built
+    pub fn build(&mut self) -> Result<BuildOutcome, Error> {
+        let mut outcome = BuildOutcome::UpToDate;
+
+        for project in self.load_projects()? {
+            outcome = outcome.max(self.build_project(&project)?);
+        }
+
+        return Ok(outcome);
+    }
+
+    fn build_project(&mut self, project: &Project) -> Result<BuildOutcome, Error> {
+        let mut passes: usize = 0;
+
+        let result = match project.can_build() {
+            Ok(_) => self.make_passes(project, &mut passes),
+            Err(error) => match BuildOutcome::from_error(&error) {
+                Some(outcome) => {
+                    self.logger.error(format!("{}", error));
+                    project.remove_sarif().map(|_| outcome)
                 }
-                _ => {}
+                None => Err(error),
             },
-            _ => {}
-        }
+        };
+
+        // an error that stops the whole build has failed this target too
+        let outcome = match result {
+            Ok(outcome) => outcome,
+            Err(_) => BuildOutcome::Failed,
+        };
 
-        let mut needs_build_checker = NeedsBuildChecker::new(&project);
+        self.logger.build_finished(project.name(), outcome, passes);
 
-        while needs_build_checker.needs_build()? {
-            self.logger.message("building project");
+        return result;
+    }
+
+    /// Make the latex passes of a project until it is up to date
+    ///
+    /// # Arguments
+    ///
+    /// - `project`: the project
+    /// - `passes`: incremented for every pass made
+    ///
+    /// # Returns
+    ///
+    /// The outcome of the project, the diagnostics of the last pass are
+    /// reported whether it has succeeded or not
+    fn make_passes(
+        &mut self,
+        project: &Project,
+        passes: &mut usize,
+    ) -> Result<BuildOutcome, Error> {
+        let mut needs_build_checker = NeedsBuildChecker::new(project);
+        let mut bibliography_builder = BibliographyBuilder::new(project);
+        let mut index_builder = IndexBuilder::new(project);
+        // the diagnostics of the last pass, `None` if it has not written a
+        // log to report
+        let mut diagnostics: Option<Vec<Diagnostic>> = None;
+        let mut outcome = BuildOutcome::Built;
+
+        loop {
+            // a build that does not stabilise has still made passes, whose
+            // diagnostics are reported like those of a failed pass
+            let reason = match needs_build_checker.needs_build() {
+                Ok(Some(reason)) => reason,
+                Ok(None) => break,
+                Err(error @ Error::TooManyPasses(_, _)) | Err(error @ Error::AuxOscillation(_)) => {
+                    self.logger.error(format!("{}", error));
+                    outcome = BuildOutcome::Failed;
+                    break;
+                }
+                Err(error) => return Err(error),
+            };
+
+            *passes += 1;
+            self.logger.pass_started(project.name(), *passes, &reason);
 
-            if !project.build(self.logger)? {
-                self.logger.error("build stopped due to error");
+            let output = match project.build(self.logger) {
+                Ok(output) => output,
+                Err(error) => match BuildOutcome::from_error(&error) {
+                    Some(failed) => {
+                        self.logger.error(format!("{}", error));
+                        outcome = failed;
+
+                        // a pass that has timed out may have written part of
+                        // its log, a missing engine has not written any
+                        diagnostics = match failed {
+                            BuildOutcome::TimedOut => Some(project.diagnostics())
+                                .filter(|diagnostics| !diagnostics.is_empty()),
+                            _ => None,
+                        };
+
+                        break;
+                    }
+                    None => return Err(error),
+                },
+            };
+
+            let has_errors = output
+                .diagnostics
+                .iter()
+                .any(|diagnostic| diagnostic.is_error());
+            diagnostics = Some(output.diagnostics);
+
+            if !output.success {
+                // without errors in the log, the document is not the cause
+                // and there is nothing to report but the program failing
+                if has_errors {
+                    self.logger.error("build stopped due to error");
+                } else {
+                    let error = Error::EngineFailed(
+                        project.latex().to_string_lossy().into_owned(),
+                        output.code,
+                    );
+                    self.logger.error(format!("{}", error));
+                }
+
+                outcome = BuildOutcome::Failed;
                 break;
             }
+
+            if project.engine().runs_to_completion() {
+                break;
+            }
+
+            if let Some(message) = output.rerun {
+                needs_build_checker.request_build(RebuildReason::RerunRequested(message));
+            }
+
+            // a bibliography that could not be built is not fixed by another
+            // pass
+            match bibliography_builder.build(self.logger)? {
+                StepOutcome::Unchanged => {}
+                StepOutcome::Changed => {
+                    needs_build_checker.request_build(RebuildReason::BibliographyChanged);
+                }
+                StepOutcome::Failed(failed) => {
+                    outcome = failed;
+                    break;
+                }
+            }
+
+            match index_builder.build(self.logger)? {
+                StepOutcome::Unchanged => {}
+                StepOutcome::Changed => {
+                    needs_build_checker.request_build(RebuildReason::IndexChanged);
+                }
+                StepOutcome::Failed(failed) => {
+                    outcome = failed;
+                    break;
+                }
+            }
         }
 
-        return Ok(());
+        if outcome == BuildOutcome::Built && *passes > 0 && !project.convert(self.logger)? {
+            self.logger.error("conversion to pdf failed");
+            outcome = BuildOutcome::Failed;
+        }
+
+        // only report the diagnostics of the last pass, as warnings such as
+        // undefined references are usually resolved by later passes
+        for diagnostic in diagnostics.iter().flatten() {
+            self.logger.diagnostic(diagnostic);
+        }
+
+        // a report without results would claim that the document is clean
+        if *passes > 0 {
+            match &diagnostics {
+                Some(diagnostics) => project.save_sarif(diagnostics, &self.root_path())?,
+                None => project.remove_sarif()?,
+            }
+
+            project.save_failure(outcome != BuildOutcome::Built)?;
+        }
+
+        if outcome == BuildOutcome::Built && project.rebuild_strategy() != RebuildStrategy::Mtime {
+            project.save_state()?;
+        }
+
+        if outcome == BuildOutcome::Built && *passes == 0 {
+            return Ok(BuildOutcome::UpToDate);
+        }
+
+        return Ok(outcome);
+    }
+
+    /// Determine why the targets would be built, without building them
+    ///
+    /// # Returns
+    ///
+    /// The reason each target needs a build
+    pub fn why(&mut self) -> Result<Vec<TargetRebuildReason>, Error> {
+        let mut reasons: Vec<TargetRebuildReason> = Vec::new();
+
+        for project in self.load_projects()? {
+            project.can_build()?;
+
+            let reason = NeedsBuildChecker::new(&project).needs_build()?;
+            reasons.push((project.name().map(String::from), reason));
+        }
+
+        return Ok(reasons);
     }
 
     pub fn clean(&mut self) -> Result<(), Error> {
-        let project = self.load_project()?;
-        self.logger.message("cleaning bin directory");
-
-        match remove_dir_all(project.bin()) {
-            Ok(_a) => {}
-            Err(err) => {
-                let message = format!("{}", err);
-                self.logger.error(&message);
+        let mut bins: Vec<PathBuf> = Vec::new();
+
+        for project in self.load_projects()? {
+            if !bins.iter().any(|bin| bin == project.bin()) {
+                bins.push(PathBuf::from(project.bin()));
+            }
+        }
+
+        for bin in bins {
+            self.logger.message("cleaning bin directory");
+
+            match remove_dir_all(&bin) {
+                Ok(_) => {}
+                // a project that has never been built has nothing to clean
+                Err(error) if error.kind() == ErrorKind::NotFound => {}
+                Err(error) => return Err(Error::IO("remove", bin, error)),
             }
         }
 
         return Ok(());
     }
 
+    /// Write a Makefile next to the config, with the commands `build` runs
+    /// from the current directory
     pub fn generate_make(&mut self) -> Result<(), Error> {
-        let makefile = Project::load(&self.config_path)?.to_make()?;
+        let projects = self.load_projects()?;
+        let makefile: Makefile = Project::projects_to_make(&projects)?;
 
-        let mut file = self.config_path.clone();
-        file.pop();
-        file.push("Makefile");
+        let mut path = self.config_path.clone();
+        path.pop();
+        path.push("Makefile");
 
-        let file = match File::create(file) {
+        let file = match File::create(&path) {
             Ok(file) => file,
-            Err(error) => return Err(Error::IO(error)),
+            Err(error) => return Err(Error::IO("create", path, error)),
         };
 
         let mut file_writer = BufWriter::new(file);
 
-        makefile.generate(&mut file_writer)?;
+        // flushing reports the errors of the last writes, which dropping the
+        // writer would ignore
+        let result = makefile
+            .generate(&mut file_writer)
+            .and_then(|_| file_writer.flush());
 
-        return Ok(());
+        return match result {
+            Ok(_) => Ok(()),
+            Err(error) => Err(Error::IO("write", path, error)),
+        };
     }
 }
@@ -1,8 +1,32 @@
+use super::{relative_to_root, BuildOutcome, Diagnostic, RebuildReason};
 use ansi_term::{Color, Style};
 use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// How much a logger shows
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+    /// Only errors
+    Quiet,
+    /// Messages such as the reason of each pass, warnings and errors
+    Normal,
+    /// Also the commands run and what they write to standard error
+    Verbose,
+    /// Also the complete output of the commands
+    VeryVerbose,
+}
 
 /// Logger
+///
+/// The events that not every logger reports, such as the diagnostics or the
+/// end of a pass, do nothing by default
 pub trait Logger {
+    /// How much the logger shows, the output of commands is only passed to
+    /// the logger if it is to be shown
+    fn verbosity(&self) -> Verbosity {
+        return Verbosity::Normal;
+    }
     /// Called when a command is run
     ///
     /// # Parameters
@@ -14,7 +38,7 @@ pub trait Logger {
         CS: AsRef<OsStr>,
         I: IntoIterator<Item = S>,
         S: AsRef<OsStr>;
-    /// Called when a command has produced outputs
+    /// Called when a command has produced outputs, line by line for latex
     ///
     /// # Parameter
     ///
@@ -22,6 +46,17 @@ pub trait Logger {
     fn command_output<S>(&mut self, s: S)
     where
         S: AsRef<str>;
+    /// Called when a command has written to its standard error, line by line
+    /// for latex
+    ///
+    /// # Parameter
+    ///
+    /// - `s`: the command error output
+    fn command_error_output<S>(&mut self, _s: S)
+    where
+        S: AsRef<str>,
+    {
+    }
     /// Called when a error occurs
     ///
     /// # Parameter
@@ -38,24 +73,118 @@ pub trait Logger {
     fn message<S>(&mut self, message: S)
     where
         S: AsRef<str>;
+    /// Called when a diagnostic has been parsed from the log file
+    ///
+    /// # Parameter
+    ///
+    /// - `diagnostic`: the diagnostic
+    fn diagnostic(&mut self, _diagnostic: &Diagnostic) {}
+    /// Called when a latex pass starts
+    ///
+    /// # Parameters
+    ///
+    /// - `target`: the name of the target, `None` if the config has no targets
+    /// - `pass`: the number of the pass, starting from 1
+    /// - `reason`: why the pass is made
+    fn pass_started(&mut self, _target: Option<&str>, _pass: usize, _reason: &RebuildReason) {}
+    /// Called when a command has exited or has been killed
+    ///
+    /// # Parameters
+    ///
+    /// - `command`: the command string
+    /// - `status`: the exit status, `None` if the command has been killed
+    /// - `duration`: how long the command has run for
+    fn command_finished<CS>(&mut self, _command: CS, _status: Option<i32>, _duration: Duration)
+    where
+        CS: AsRef<OsStr>,
+    {
+    }
+    /// Called when the build of a target has finished
+    ///
+    /// # Parameters
+    ///
+    /// - `target`: the name of the target, `None` if the config has no targets
+    /// - `outcome`: the outcome of the target
+    /// - `passes`: the number of latex passes made
+    fn build_finished(&mut self, _target: Option<&str>, _outcome: BuildOutcome, _passes: usize) {}
+}
+
+/// How `StdErrLogger` prints diagnostics
+#[derive(Clone, Debug, PartialEq)]
+pub enum DiagnosticFormat {
+    /// `==> error: message (file, line 12)`, in color
+    Human,
+    /// `file:12: error: message`, as printed by gcc and understood by
+    /// editors, with the file relative to the given project root
+    Short(PathBuf),
 }
 
 /// `trait Logger` implementation for standard error
-pub struct StdErrLogger {}
+pub struct StdErrLogger {
+    verbosity: Verbosity,
+    diagnostic_format: DiagnosticFormat,
+}
 
 impl StdErrLogger {
     pub fn new() -> StdErrLogger {
-        StdErrLogger {}
+        StdErrLogger {
+            verbosity: Verbosity::Normal,
+            diagnostic_format: DiagnosticFormat::Human,
+        }
+    }
+
+    pub fn with_verbosity(verbosity: Verbosity) -> StdErrLogger {
+        StdErrLogger {
+            verbosity,
+            diagnostic_format: DiagnosticFormat::Human,
+        }
+    }
+
+    pub fn with_diagnostic_format(
+        verbosity: Verbosity,
+        diagnostic_format: DiagnosticFormat,
+    ) -> StdErrLogger {
+        StdErrLogger {
+            verbosity,
+            diagnostic_format,
+        }
     }
 }
 
+/// Format a diagnostic like gcc does, `file:line: error: message`
+///
+/// # Arguments
+///
+/// - `diagnostic`: the diagnostic
+/// - `root`: the project root the file is made relative to
+fn short_diagnostic(diagnostic: &Diagnostic, root: &Path) -> String {
+    return match (&diagnostic.file, diagnostic.line) {
+        (Some(file), Some(line)) => format!(
+            "{}:{}: {}",
+            relative_to_root(file, root).display(),
+            line,
+            diagnostic
+        ),
+        (Some(file), None) => format!("{}: {}", relative_to_root(file, root).display(), diagnostic),
+        _ => format!("{}", diagnostic),
+    };
+}
+
 impl Logger for StdErrLogger {
+    fn verbosity(&self) -> Verbosity {
+        return self.verbosity;
+    }
+
     fn run_command<CS, I, S>(&mut self, command: CS, args: I)
     where
         CS: AsRef<OsStr>,
         I: IntoIterator<Item = S>,
         S: AsRef<OsStr>,
     {
+        if self.verbosity < Verbosity::Verbose {
+            return;
+        }
+
         let mut raw_output = String::new();
         let s = match command.as_ref().to_str() {
             Some(s) => s,
@@ -84,8 +213,14 @@ impl Logger for StdErrLogger {
         S: AsRef<str>,
     {
         let output = Style::new().dimmed().paint(s.as_ref());
+        eprintln!("{}", output);
+    }
 
-        eprintln!();
+    fn command_error_output<S>(&mut self, s: S)
+    where
+        S: AsRef<str>,
+    {
+        let output = Color::Red.dimmed().paint(s.as_ref());
         eprintln!("{}", output);
     }
 
@@ -102,6 +237,130 @@ impl Logger for StdErrLogger {
     where
         S: AsRef<str>,
     {
+        if self.verbosity < Verbosity::Normal {
+            return;
+        }
+
         eprintln!("==> {}", message.as_ref());
     }
+
+    fn diagnostic(&mut self, diagnostic: &Diagnostic) {
+        if !diagnostic.is_error() && self.verbosity < Verbosity::Normal {
+            return;
+        }
+
+        // editors read the lines without colors
+        if let DiagnosticFormat::Short(root) = &self.diagnostic_format {
+            eprintln!("{}", short_diagnostic(diagnostic, root));
+            return;
+        }
+
+        let mut raw_output = format!("==> {}", diagnostic);
+
+        match (&diagnostic.file, diagnostic.line) {
+            (Some(file), Some(line)) => {
+                raw_output.push_str(&format!(" ({}, line {})", file.display(), line));
+            }
+            (Some(file), None) => {
+                raw_output.push_str(&format!(" ({})", file.display()));
+            }
+            _ => {}
+        }
+
+        let output = if diagnostic.is_error() {
+            Color::Red.paint(raw_output)
+        } else {
+            Color::Yellow.paint(raw_output)
+        };
+
+        eprintln!("{}", output);
+    }
+
+    fn pass_started(&mut self, target: Option<&str>, _pass: usize, reason: &RebuildReason) {
+        let target = target.unwrap_or("project");
+        self.message(format!("building {}: {}", target, reason));
+    }
+
+    fn command_finished<CS>(&mut self, command: CS, status: Option<i32>, duration: Duration)
+    where
+        CS: AsRef<OsStr>,
+    {
+        if self.verbosity < Verbosity::Verbose {
+            return;
+        }
+
+        let status = match status {
+            Some(status) => format!("exited with {}", status),
+            None => String::from("was killed"),
+        };
+
+        let raw_output = format!(
+            "{} {} after {:.2}s",
+            command.as_ref().to_string_lossy(),
+            status,
+            duration.as_secs_f64()
+        );
+
+        eprintln!("{}", Style::new().dimmed().paint(raw_output));
+    }
+
+    fn build_finished(&mut self, _target: Option<&str>, _outcome: BuildOutcome, _passes: usize) {
+        // errors are printed as they happen
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DiagnosticKind;
+
+    fn diagnostic(file: Option<&str>, line: Option<usize>) -> Diagnostic {
+        return Diagnostic {
+            kind: DiagnosticKind::Error,
+            file: file.map(PathBuf::from),
+            line,
+            message: String::from("Undefined control sequence."),
+        };
+    }
+
+    #[test]
+    fn short_diagnostic_has_file_and_line() {
+        assert_eq!(
+            short_diagnostic(
+                &diagnostic(Some("paper/chapters/intro.tex"), Some(12)),
+                Path::new("paper")
+            ),
+            "chapters/intro.tex:12: error: Undefined control sequence."
+        );
+    }
+
+    #[test]
+    fn short_diagnostic_has_file_without_line() {
+        assert_eq!(
+            short_diagnostic(
+                &diagnostic(Some("paper/index.tex"), None),
+                Path::new("paper")
+            ),
+            "index.tex: error: Undefined control sequence."
+        );
+    }
+
+    #[test]
+    fn short_diagnostic_without_file_has_message() {
+        assert_eq!(
+            short_diagnostic(&diagnostic(None, Some(12)), Path::new("paper")),
+            "error: Undefined control sequence."
+        );
+    }
+
+    #[test]
+    fn short_diagnostic_outside_root_keeps_file() {
+        assert_eq!(
+            short_diagnostic(
+                &diagnostic(Some("/usr/share/texlive/article.cls"), Some(3)),
+                Path::new("paper")
+            ),
+            "/usr/share/texlive/article.cls:3: error: Undefined control sequence."
+        );
+    }
 }
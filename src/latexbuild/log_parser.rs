@@ -0,0 +1,565 @@
+use std::fmt::{self, Display, Formatter};
+use std::path::{Path, PathBuf};
+
+/// TeX wraps lines of the log file at this many bytes, or characters for
+/// xetex
+const MAX_PRINT_LINE: usize = 79;
+
+/// How far after a `!` error line to look for the `l.<line>` context line
+const ERROR_CONTEXT_LINES: usize = 12;
+
+/// The kind of a diagnostic found in a log file
+#[derive(Clone, Debug, PartialEq)]
+pub enum DiagnosticKind {
+    /// A TeX or LaTeX error, for example `! Undefined control sequence.`
+    Error,
+    /// `Overfull \hbox` or `Overfull \vbox`
+    OverfullBox,
+    /// `Underfull \hbox` or `Underfull \vbox`
+    UnderfullBox,
+    /// A `\ref` to a label that is not defined, with the name of the label
+    UndefinedReference(String),
+    /// A `\cite` of a key that is not defined, with the citation key
+    UndefinedCitation(String),
+    /// A font shape that is not available and has been substituted
+    FontSubstitution,
+    /// A warning from a package or a class, with the name of the package
+    PackageWarning(String),
+    /// Any other warning from LaTeX
+    LatexWarning,
+}
+
+impl DiagnosticKind {
+    pub fn name(&self) -> &'static str {
+        return match self {
+            DiagnosticKind::Error => "error",
+            DiagnosticKind::OverfullBox => "overfull-box",
+            DiagnosticKind::UnderfullBox => "underfull-box",
+            DiagnosticKind::UndefinedReference(_) => "undefined-reference",
+            DiagnosticKind::UndefinedCitation(_) => "undefined-citation",
+            DiagnosticKind::FontSubstitution => "font-substitution",
+            DiagnosticKind::PackageWarning(_) => "package-warning",
+            DiagnosticKind::LatexWarning => "latex-warning",
+        };
+    }
+}
+
+/// A diagnostic parsed from a log file
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnostic {
+    pub kind: DiagnosticKind,
+    /// The source file the diagnostic is attributed to, as written in the log
+    pub file: Option<PathBuf>,
+    /// The line in the source file
+    pub line: Option<usize>,
+    /// The message, with continuation lines joined
+    pub message: String,
+}
+
+/// Phrases of the warnings LaTeX, hyperref, longtable, rerunfilecheck and
+/// biblatex print when another pass is needed
+const RERUN_PHRASES: [&str; 4] = ["Rerun to get", "Rerun LaTeX", "rerun LaTeX", "Please rerun"];
+
+impl Diagnostic {
+    pub fn is_error(&self) -> bool {
+        return self.kind == DiagnosticKind::Error;
+    }
+
+    /// Determine if the diagnostic is a warning asking for another pass, for
+    /// example `Label(s) may have changed. Rerun to get cross-references
+    /// right.`
+    pub fn requests_rerun(&self) -> bool {
+        return !self.is_error()
+            && RERUN_PHRASES
+                .iter()
+                .any(|phrase| self.message.contains(phrase));
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        if self.is_error() {
+            write!(f, "error: {}", self.message)
+        } else {
+            write!(f, "warning: {}", self.message)
+        }
+    }
+}
+
+/// Join the lines TeX has wrapped at `MAX_PRINT_LINE`
+fn unwrap_lines(log: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    let mut current = String::new();
+
+    for line in log.lines() {
+        current.push_str(line);
+
+        // pdftex and luatex count the bytes of utf-8 content, xetex counts
+        // the characters
+        if line.len() != MAX_PRINT_LINE && line.chars().count() != MAX_PRINT_LINE {
+            lines.push(current);
+            current = String::new();
+        }
+    }
+
+    if !current.is_empty() {
+        lines.push(current);
+    }
+
+    return lines;
+}
+
+/// Parse the number in `on input line 12.` or `at lines 12--14`
+fn parse_line_number(message: &str) -> Option<usize> {
+    for marker in &["on input line ", "at lines ", "at line "] {
+        if let Some(index) = message.rfind(marker) {
+            let digits: String = message[index + marker.len()..]
+                .chars()
+                .take_while(|c| c.is_ascii_digit())
+                .collect();
+
+            return digits.parse().ok();
+        }
+    }
+
+    return None;
+}
+
+/// Parse the name between a backtick and a quote, for example `foo` in
+/// ``Reference `foo' on page 1 undefined``
+fn parse_quoted(message: &str) -> String {
+    let start = match message.find('`') {
+        Some(start) => start + 1,
+        None => return String::new(),
+    };
+
+    return match message[start..].find('\'') {
+        Some(end) => String::from(&message[start..start + end]),
+        None => String::new(),
+    };
+}
+
+/// Parse a `file:line: message` error, as printed with `-file-line-error`
+fn parse_file_line_error(line: &str) -> Option<(PathBuf, usize, String)> {
+    // the file can contain `:`, as in `C:\thesis\main.tex`, so it ends at
+    // the first `:` followed by a line number and another `:`
+    for (index, _) in line.match_indices(':') {
+        let rest = &line[index + 1..];
+        let digits_end = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len());
+
+        if digits_end == 0 || !rest[digits_end..].starts_with(':') {
+            continue;
+        }
+
+        let file = &line[..index];
+
+        if file.is_empty() || !file.contains('.') {
+            return None;
+        }
+
+        let line_number = rest[..digits_end].parse().ok()?;
+        let message = rest[digits_end + 1..].trim();
+
+        return Some((PathBuf::from(file), line_number, String::from(message)));
+    }
+
+    return None;
+}
+
+/// Parse the header of a warning, returning the prefix continuation lines
+/// start with and the kind of warning if it is not decided by the message
+fn parse_warning_header(line: &str) -> Option<(String, Option<String>, usize)> {
+    if line.starts_with("LaTeX Font Warning: ") {
+        return Some((String::from("(Font)"), None, "LaTeX Font Warning: ".len()));
+    }
+
+    for source in &["Package ", "Class "] {
+        if !line.starts_with(source) {
+            continue;
+        }
+
+        let rest = &line[source.len()..];
+        let name_end = rest.find(' ')?;
+        let name = &rest[..name_end];
+
+        if rest[name_end..].starts_with(" Warning: ") {
+            let start = source.len() + name_end + " Warning: ".len();
+            return Some((format!("({})", name), Some(String::from(name)), start));
+        }
+    }
+
+    if line.starts_with("LaTeX Warning: ") {
+        return Some((String::new(), None, "LaTeX Warning: ".len()));
+    }
+
+    return None;
+}
+
+/// Keeps track of which file TeX is reading from the `(file` and `)` in the
+/// log
+struct FileStack {
+    files: Vec<Option<PathBuf>>,
+}
+
+impl FileStack {
+    fn current(&self) -> Option<PathBuf> {
+        return self.files.iter().rev().flatten().next().cloned();
+    }
+
+    fn scan(&mut self, line: &str) {
+        let mut chars = line.char_indices().peekable();
+
+        while let Some((index, c)) = chars.next() {
+            match c {
+                '(' => {
+                    let rest = &line[index + 1..];
+                    let end = rest
+                        .find(|c: char| c.is_whitespace() || c == '(' || c == ')')
+                        .unwrap_or(rest.len());
+                    let name = &rest[..end];
+
+                    if looks_like_path(name) {
+                        self.files.push(Some(PathBuf::from(name)));
+
+                        // skip over the file name so that parentheses in it
+                        // are not counted
+                        while let Some(&(next, _)) = chars.peek() {
+                            if next > index + end {
+                                break;
+                            }
+
+                            chars.next();
+                        }
+                    } else {
+                        self.files.push(None);
+                    }
+                }
+                ')' => {
+                    self.files.pop();
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+fn looks_like_path(name: &str) -> bool {
+    if name.is_empty() {
+        return false;
+    }
+
+    let path = Path::new(name);
+
+    return path.extension().is_some() || name.starts_with('.') || name.starts_with('/');
+}
+
+/// Parse the diagnostics out of the content of a LaTeX log file
+///
+/// # Arguments
+///
+/// - `log`: the content of the log file
+pub fn parse_log(log: &str) -> Vec<Diagnostic> {
+    let lines = unwrap_lines(log);
+    let mut diagnostics: Vec<Diagnostic> = Vec::new();
+    let mut file_stack = FileStack { files: Vec::new() };
+    let mut index = 0;
+
+    while index < lines.len() {
+        let line = &lines[index];
+        index += 1;
+
+        // TeX errors, the line number is in the `l.<line>` context line
+        if let Some(message) = line.strip_prefix("! ") {
+            let mut diagnostic = Diagnostic {
+                kind: DiagnosticKind::Error,
+                file: file_stack.current(),
+                line: None,
+                message: String::from(message.trim()),
+            };
+
+            for offset in 0..ERROR_CONTEXT_LINES {
+                let context = match lines.get(index + offset) {
+                    Some(context) => context,
+                    None => break,
+                };
+
+                if let Some(context) = context.strip_prefix("l.") {
+                    let digits: String =
+                        context.chars().take_while(|c| c.is_ascii_digit()).collect();
+
+                    diagnostic.line = digits.parse().ok();
+
+                    // the context line and the line after it contain source
+                    // code, which must not be scanned for parentheses
+                    index += offset + 2;
+                    break;
+                }
+            }
+
+            diagnostics.push(diagnostic);
+            continue;
+        }
+
+        // errors printed with -file-line-error
+        if let Some((file, line_number, message)) = parse_file_line_error(line) {
+            diagnostics.push(Diagnostic {
+                kind: DiagnosticKind::Error,
+                file: Some(file),
+                line: Some(line_number),
+                message,
+            });
+
+            // skip to the context line, the same as with `!` errors
+            for offset in 0..ERROR_CONTEXT_LINES {
+                match lines.get(index + offset) {
+                    Some(context) if context.starts_with("l.") => {
+                        index += offset + 2;
+                        break;
+                    }
+                    Some(_) => {}
+                    None => break,
+                }
+            }
+
+            continue;
+        }
+
+        // box warnings
+        if line.starts_with("Overfull \\") || line.starts_with("Underfull \\") {
+            let kind = if line.starts_with("Overfull") {
+                DiagnosticKind::OverfullBox
+            } else {
+                DiagnosticKind::UnderfullBox
+            };
+
+            diagnostics.push(Diagnostic {
+                kind,
+                file: file_stack.current(),
+                line: parse_line_number(line),
+                message: line.clone(),
+            });
+
+            // the following lines show the content of the box, which must
+            // not be scanned for parentheses, and are terminated by an empty
+            // line
+            for offset in 0..ERROR_CONTEXT_LINES {
+                match lines.get(index + offset) {
+                    Some(content) if content.is_empty() => {
+                        index += offset + 1;
+                        break;
+                    }
+                    Some(_) => {}
+                    None => break,
+                }
+            }
+
+            continue;
+        }
+
+        // LaTeX, package and class warnings
+        if let Some((prefix, package, start)) = parse_warning_header(line) {
+            let mut message = String::from(line[start..].trim());
+
+            while let Some(next) = lines.get(index) {
+                let continuation = if prefix.is_empty() {
+                    next.starts_with("   ") && !next.trim().is_empty()
+                } else {
+                    next.starts_with(&prefix)
+                };
+
+                if !continuation {
+                    break;
+                }
+
+                message.push(' ');
+                message.push_str(next[prefix.len()..].trim());
+                index += 1;
+            }
+
+            let kind = if line.starts_with("LaTeX Font Warning: ") {
+                DiagnosticKind::FontSubstitution
+            } else if message.starts_with("Reference `") && message.contains("undefined") {
+                DiagnosticKind::UndefinedReference(parse_quoted(&message))
+            } else if message.starts_with("Citation `") && message.contains("undefined") {
+                DiagnosticKind::UndefinedCitation(parse_quoted(&message))
+            } else if let Some(package) = package {
+                DiagnosticKind::PackageWarning(package)
+            } else {
+                DiagnosticKind::LatexWarning
+            };
+
+            diagnostics.push(Diagnostic {
+                kind,
+                file: file_stack.current(),
+                line: parse_line_number(&message),
+                message,
+            });
+
+            continue;
+        }
+
+        file_stack.scan(line);
+    }
+
+    return diagnostics;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The log of a pdflatex pass run with `-file-line-error`, with a
+    /// wrapped utf-8 file name, warnings and errors
+    const PDFLATEX_LOG: &str = include_str!("../../tests/fixtures/pdflatex.log");
+
+    const CHAPTER: &str =
+        "./chapters/introduction-to-the-very-long-named-chapter-about-résumé-écritures.tex";
+
+    fn diagnostic(
+        kind: DiagnosticKind,
+        file: &str,
+        line: Option<usize>,
+        message: &str,
+    ) -> Diagnostic {
+        return Diagnostic {
+            kind,
+            file: Some(PathBuf::from(file)),
+            line,
+            message: String::from(message),
+        };
+    }
+
+    #[test]
+    fn unwrap_lines_joins_lines_of_max_print_line_bytes() {
+        let wrapped = format!("{}\n{}\nnext", "é".repeat(39) + "a", "bc");
+        assert_eq!("é".repeat(39).len() + 1, MAX_PRINT_LINE);
+
+        let lines = unwrap_lines(&wrapped);
+
+        assert_eq!(lines, vec!["é".repeat(39) + "abc", String::from("next")]);
+    }
+
+    #[test]
+    fn unwrap_lines_keeps_shorter_lines() {
+        let lines = unwrap_lines("(./main.tex\n)\n");
+
+        assert_eq!(lines, vec!["(./main.tex", ")"]);
+    }
+
+    #[test]
+    fn file_stack_follows_opened_and_closed_files() {
+        let mut file_stack = FileStack { files: Vec::new() };
+
+        file_stack.scan("(./main.tex");
+        file_stack.scan("(/usr/share/texlive/texmf-dist/tex/latex/base/article.cls");
+        assert_eq!(
+            file_stack.current(),
+            Some(PathBuf::from(
+                "/usr/share/texlive/texmf-dist/tex/latex/base/article.cls"
+            ))
+        );
+
+        file_stack.scan(") (see the transcript)");
+        assert_eq!(file_stack.current(), Some(PathBuf::from("./main.tex")));
+
+        file_stack.scan(")");
+        assert_eq!(file_stack.current(), None);
+    }
+
+    #[test]
+    fn parse_file_line_error_with_drive_letter() {
+        let parsed =
+            parse_file_line_error("C:\\thesis\\main.tex:12: Undefined control sequence: \\foo.");
+
+        assert_eq!(
+            parsed,
+            Some((
+                PathBuf::from("C:\\thesis\\main.tex"),
+                12,
+                String::from("Undefined control sequence: \\foo.")
+            ))
+        );
+    }
+
+    #[test]
+    fn parse_file_line_error_ignores_other_lines() {
+        assert_eq!(
+            parse_file_line_error("LaTeX Font Info:    ... okay on input line 5."),
+            None
+        );
+        assert_eq!(parse_file_line_error("Started at 10:31"), None);
+    }
+
+    #[test]
+    fn parse_log_of_pdflatex() {
+        let diagnostics = parse_log(PDFLATEX_LOG);
+
+        assert_eq!(
+            diagnostics,
+            vec![
+                diagnostic(
+                    DiagnosticKind::UndefinedReference(String::from("fig:missing")),
+                    CHAPTER,
+                    Some(3),
+                    "Reference `fig:missing' on page 1 undefined on input line 3.",
+                ),
+                diagnostic(
+                    DiagnosticKind::OverfullBox,
+                    CHAPTER,
+                    Some(5),
+                    "Overfull \\hbox (15.0pt too wide) in paragraph at lines 5--6",
+                ),
+                diagnostic(
+                    DiagnosticKind::Error,
+                    "./main.tex",
+                    Some(9),
+                    "Undefined control sequence.",
+                ),
+                diagnostic(
+                    DiagnosticKind::UndefinedCitation(String::from("knuth1984")),
+                    "./main.tex",
+                    Some(11),
+                    "Citation `knuth1984' on page 1 undefined on input line 11.",
+                ),
+                diagnostic(
+                    DiagnosticKind::PackageWarning(String::from("hyperref")),
+                    "./main.tex",
+                    Some(13),
+                    "Token not allowed in a PDF string (Unicode): removing `math shift' on input line 13.",
+                ),
+                diagnostic(
+                    DiagnosticKind::Error,
+                    "./main.tex",
+                    Some(15),
+                    "LaTeX Error: Environment itemise undefined.",
+                ),
+                diagnostic(
+                    DiagnosticKind::LatexWarning,
+                    "./main.tex",
+                    None,
+                    "There were undefined references.",
+                ),
+                diagnostic(
+                    DiagnosticKind::LatexWarning,
+                    "./main.tex",
+                    None,
+                    "Label(s) may have changed. Rerun to get cross-references right.",
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_log_finds_rerun_requests() {
+        let diagnostics = parse_log(PDFLATEX_LOG);
+        let reruns: Vec<&Diagnostic> = diagnostics
+            .iter()
+            .filter(|diagnostic| diagnostic.requests_rerun())
+            .collect();
+
+        assert_eq!(reruns.len(), 1);
+        assert!(reruns[0].message.starts_with("Label(s) may have changed."));
+    }
+}
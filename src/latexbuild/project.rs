@@ -1,46 +1,12 @@
-use super::Error;
-use json::object::Object;
-use json::{parse, JsonValue};
-use std::ffi::{OsStr, OsString};
-use std::fs::{read, read_dir};
+use super::{
+    resolve_includes, Bibliography, Config, Engine, EngineOutput, Error, Index, Interaction,
+    RebuildStrategy, ShellEscape,
+};
+use std::collections::BTreeMap;
+use std::env::current_dir;
+use std::ffi::OsStr;
 use std::path::{Path, PathBuf};
-
-fn resolve_includes(includes: Vec<PathBuf>) -> Result<Vec<PathBuf>, Error> {
-    let mut files: Vec<PathBuf> = Vec::new();
-    let mut to_explore: Vec<PathBuf> = Vec::new();
-
-    for include in includes {
-        if include.is_dir() {
-            to_explore.push(include);
-        } else {
-            files.push(include);
-        }
-    }
-
-    while let Some(dir) = to_explore.pop() {
-        match read_dir(&dir) {
-            Ok(dir) => {
-                for dir_item in dir {
-                    match dir_item {
-                        Ok(dir_item) => {
-                            let dir_item = dir_item.path();
-
-                            if dir_item.is_dir() {
-                                to_explore.push(dir_item);
-                            } else {
-                                files.push(dir_item);
-                            }
-                        }
-                        Err(error) => return Err(Error::IO(error)),
-                    }
-                }
-            }
-            Err(_) => return Err(Error::PathNotFound(dir)),
-        }
-    }
-
-    return Ok(files);
-}
+use std::time::Duration;
 
 /// A project loaded from disk
 ///
@@ -50,6 +16,8 @@ fn resolve_includes(includes: Vec<PathBuf>) -> Result<Vec<PathBuf>, Error> {
 ///   instead, it is inferred from the project specification
 /// - `aux`: note that the aux field is not in the project specification,
 ///   instead, it is inferred from the project specification
+/// - `log`: note that the log field is not in the project specification,
+///   instead, it is inferred from the project specification
 ///
 /// # Discussion
 ///
@@ -57,18 +25,83 @@ fn resolve_includes(includes: Vec<PathBuf>) -> Result<Vec<PathBuf>, Error> {
 /// edited by the consumer. In the future, the fields would be replaced by
 /// getters and setters
 pub struct Project {
-    /// The `latex` program used
-    latex: OsString,
+    /// The name of the target, `None` if the config has no targets
+    name: Option<String>,
+    /// The name of the output files
+    jobname: String,
+    /// The engine used
+    engine: Engine,
+    /// How latex interacts with the user on errors
+    interaction: Interaction,
+    /// Write a synctex file
+    synctex: bool,
+    /// Whether the document can run external programs
+    shell_escape: Option<ShellEscape>,
+    /// Print errors as `file:line: error`
+    file_line_error: bool,
+    /// Which lines of the output of latex are logged regardless of the
+    /// verbosity
+    engine_output: Option<EngineOutput>,
+    /// The longest a latex pass can take before it is killed
+    timeout: Option<Duration>,
+    /// The extra arguments passed to `latex`
+    args: Vec<String>,
+    /// The macros defined before the entry is input
+    defines: BTreeMap<String, String>,
     /// The output directory
     bin: PathBuf,
     /// The pdf file
     pdf: PathBuf,
     /// The aux file
     aux: PathBuf,
+    /// The log file
+    log: PathBuf,
     /// The entry latex file
     entry: PathBuf,
     /// The include files and directories
     files: Vec<PathBuf>,
+    /// The program used to process the bibliography, detected if not set
+    bibliography: Option<Bibliography>,
+    /// The program used to process the index
+    index: Index,
+    /// The style file passed to the index program
+    index_style: Option<PathBuf>,
+    /// The style file passed to makeglossaries
+    glossary_style: Option<PathBuf>,
+    /// How to determine if the sources have changed
+    rebuild_strategy: RebuildStrategy,
+    /// The maximum number of latex passes in one build
+    max_passes: usize,
+    /// The extensions of the generated files that need another pass when
+    /// they change, in addition to the aux files
+    tracked_extensions: Vec<String>,
+}
+
+/// Make a path absolute using the current directory, for programs that run
+/// inside the bin directory
+pub(crate) fn absolute(path: &Path) -> PathBuf {
+    let path = match current_dir() {
+        Ok(current_dir) => current_dir.join(path),
+        Err(_) => PathBuf::from(path),
+    };
+
+    // collecting the components drops the `.` in paths such as `./index.tex`
+    return path.components().collect();
+}
+
+/// Make a file written in a log relative to the project root, files outside
+/// of it are left as they are
+///
+/// # Arguments
+///
+/// - `file`: the file, relative to the current directory like latex writes
+///   it, or absolute
+/// - `root`: the project root
+pub(crate) fn relative_to_root(file: &Path, root: &Path) -> PathBuf {
+    return match absolute(file).strip_prefix(absolute(root)) {
+        Ok(relative) => PathBuf::from(relative),
+        Err(_) => PathBuf::from(file),
+    };
 }
 
 fn with_prepend(path: &PathBuf, prepend: &Path) -> PathBuf {
@@ -79,174 +112,179 @@ fn with_prepend(path: &PathBuf, prepend: &Path) -> PathBuf {
 }
 
 impl Project {
-    /// Create a new project with the following default values
-    ///
-    /// - `latex`: `pdflatex`
-    /// - `bin`: `bin`
-    /// - `pdf`: `index.pdf`
-    /// - `entry`: `index.tex`
-    /// - `includes`: []
+    /// Create a new project using the default config, see `Config::default`
     pub fn new() -> Project {
-        let mut files: Vec<PathBuf> = Vec::new();
-        files.push(PathBuf::from("index.tex"));
+        let config = Config::default();
+        let mut files = config.includes.clone();
+        files.push(config.entry.clone());
+
+        let engine = Engine::from_name(&config.latex);
+
+        return Project::with_files(config, engine, files);
+    }
+
+    /// Create a project from a config, resolving the includes
+    ///
+    /// # Arguments
+    ///
+    /// - `config`: the config
+    /// - `root`: the directory the includes are relative to, usually the
+    ///   directory of the config
+    ///
+    /// # Returns
+    ///
+    /// `Err(Error::InvalidConfig)` if the config does not validate, see
+    /// `Config::validate`, or if it has defines and the engine is tectonic
+    pub fn from_config(config: Config, root: &Path) -> Result<Project, Error> {
+        config.validate()?;
+
+        let mut files = resolve_includes(root, &config.includes, &config.excludes)?;
+
+        if !files.contains(&config.entry) {
+            files.push(config.entry.clone());
+        }
+
+        let engine = Engine::from_config(&config.latex, &root.join(&config.entry));
+
+        // the defines are passed as tex code, which tectonic does not take
+        if engine == Engine::Tectonic && !config.defines.is_empty() {
+            return Err(Error::InvalidConfig(String::from(
+                "tectonic does not support defines",
+            )));
+        }
+
+        return Ok(Project::with_files(config, engine, files));
+    }
+
+    fn with_files(config: Config, engine: Engine, files: Vec<PathBuf>) -> Project {
+        // After getting entry, resolve
+        // - pdf
+        // - aux
+        // - log
+        let entry_stem = match config.entry.file_stem() {
+            Some(stem) => stem.to_string_lossy().into_owned(),
+            None => String::new(),
+        };
+
+        // tectonic cannot be given a job name
+        let jobname = match &config.output {
+            Some(output) if engine != Engine::Tectonic => output.clone(),
+            _ => entry_stem,
+        };
+
+        let pdf = config.bin.join(format!("{}.pdf", jobname));
+        let aux = config.bin.join(format!("{}.aux", jobname));
+        let log = config.bin.join(format!("{}.log", jobname));
 
         Project {
-            latex: OsString::from("pdflatex"),
-            bin: PathBuf::from("bin"),
-            pdf: PathBuf::from("bin/index.pdf"),
-            aux: PathBuf::from("bin/index.aux"),
-            entry: PathBuf::from("index.tex"),
+            name: None,
+            jobname,
+            engine,
+            interaction: config.interaction,
+            synctex: config.synctex,
+            shell_escape: config.shell_escape,
+            file_line_error: config.file_line_error,
+            engine_output: config.engine_output,
+            timeout: config.timeout.map(Duration::from_secs),
+            args: config.args,
+            defines: config.defines,
+            bin: config.bin,
+            pdf,
+            aux,
+            log,
+            entry: config.entry,
             files,
+            bibliography: config.bibliography,
+            index: config.index,
+            index_style: config.index_style,
+            glossary_style: config.glossary_style,
+            rebuild_strategy: config.rebuild_strategy,
+            max_passes: config.max_passes.get(),
+            tracked_extensions: config.tracked_extensions,
         }
     }
 
-    /// Load a project from a json path
+    /// Load a project from a json, toml or yaml config, the first target if
+    /// the config has targets, see `load_targets` to load all of them
     ///
     /// # Arguments
     ///
-    /// - `path`: the path to the json
+    /// - `path`: the path to the config
     pub fn load<P: AsRef<Path>>(path: &P) -> Result<Project, Error> {
-        let mut project = Project {
-            latex: OsString::new(),
-            bin: PathBuf::new(),
-            pdf: PathBuf::new(),
-            aux: PathBuf::new(),
-            entry: PathBuf::new(),
-            files: Vec::new(),
-        };
+        let mut projects = Project::load_targets(path, None, None)?;
 
-        let file_content: String;
+        return Ok(projects.remove(0));
+    }
 
-        match read(path) {
-            Ok(raw_content) => {
-                file_content = match String::from_utf8(raw_content) {
-                    Ok(s) => s,
-                    Err(_) => return Err(Error::Encoding),
-                };
-            }
-            Err(_) => {
-                return Err(Error::PathNotFound(PathBuf::from(path.as_ref())));
-            }
-        }
+    /// Load the projects of the targets of a json, toml or yaml config, or
+    /// the only project of a config without targets
+    ///
+    /// # Arguments
+    ///
+    /// - `path`: the path to the config
+    /// - `target`: the target to load, all of them if `None`
+    /// - `profile`: the profile to apply, if any
+    ///
+    /// # Returns
+    ///
+    /// `Err(Error::UnknownTarget)` if `target` is not in the config,
+    /// `Err(Error::UnknownProfile)` if `profile` is not in the config, and
+    /// `Err(Error::InvalidConfig)` if two targets write the same pdf
+    pub fn load_targets<P: AsRef<Path>>(
+        path: &P,
+        target: Option<&str>,
+        profile: Option<&str>,
+    ) -> Result<Vec<Project>, Error> {
+        let root = match path.as_ref().parent() {
+            Some(root) => PathBuf::from(root),
+            None => PathBuf::new(),
+        };
 
-        if file_content.is_empty() {
-            return Ok(project);
+        let mut config = Config::load(path)?;
+
+        if let Some(profile) = profile {
+            config = config.profile(profile)?;
         }
 
-        let parsed = match parse(&file_content) {
-            Ok(json_value) => json_value,
-            Err(error) => return Err(Error::JsonParsing(error)),
+        let names: Vec<String> = match target {
+            Some(target) => vec![String::from(target)],
+            None => config.targets.keys().cloned().collect(),
         };
 
-        return match parsed {
-            JsonValue::Object(object) => {
-                // latex
-                match object.get("latex") {
-                    Some(latex) => match latex {
-                        JsonValue::Short(latex_short) => {
-                            project.latex = OsString::from(latex_short.as_str());
-                        }
-                        JsonValue::String(latex_str) => {
-                            project.latex = OsString::from(latex_str);
-                        }
-                        _ => {
-                            return Err(Error::WrongConfigFormat(String::from(
-                                "\"latex\" should be a string",
-                            )));
-                        }
-                    },
-                    _ => {
-                        project.latex = OsString::from("pdflatex");
-                    }
-                }
-
-                // bin
-                match object.get("bin") {
-                    Some(bin) => match bin {
-                        JsonValue::Short(bin_short) => {
-                            project.bin = PathBuf::from(bin_short.as_str());
-                        }
-                        JsonValue::String(bin_str) => {
-                            project.bin = PathBuf::from(bin_str);
-                        }
-                        _ => {
-                            return Err(Error::WrongConfigFormat(String::from(
-                                "\"bin\" should be a string",
-                            )));
-                        }
-                    },
-                    _ => {
-                        project.bin = PathBuf::from("bin");
-                    }
-                }
-
-                // entry
-                match object.get("entry") {
-                    Some(entry) => match entry {
-                        JsonValue::Short(entry_short) => {
-                            project.entry = PathBuf::from(entry_short.as_str());
-                        }
-                        JsonValue::String(entry_str) => {
-                            project.entry = PathBuf::from(entry_str);
-                        }
-                        _ => {
-                            return Err(Error::WrongConfigFormat(String::from(
-                                "\"entry\" should be a string",
-                            )));
-                        }
-                    },
-                    _ => {
-                        project.entry = PathBuf::from("index.tex");
-                    }
-                }
-
-                // After getting entry, resolve
-                // - pdf
-                // - aux
-                project.pdf = project.bin.clone();
-                project.pdf.push(&project.entry);
-                project.pdf.set_extension("pdf");
-
-                project.aux = project.bin.clone();
-                project.aux.push(&project.entry);
-                project.aux.set_extension("aux");
-
-                // includes
-                match object.get("includes") {
-                    Some(includes) => match includes {
-                        JsonValue::Array(includes_array) => {
-                            for include in includes_array {
-                                match include {
-                                    JsonValue::Short(include_short) => {
-                                        project.files.push(PathBuf::from(include_short.as_str()));
-                                    }
-                                    JsonValue::String(include_str) => {
-                                        project.files.push(PathBuf::from(include_str));
-                                    }
-                                    _ => {
-                                        return Err(Error::WrongConfigFormat(String::from(
-                                            "items in \"includes\" strings",
-                                        )));
-                                    }
-                                }
-                            }
-                        }
-                        _ => {
-                            return Err(Error::WrongConfigFormat(String::from(
-                                "\"includes\" should be an array",
-                            )));
-                        }
-                    },
-                    _ => {}
-                }
-
-                project.files.push(project.entry.clone());
-                project.files = resolve_includes(project.files)?;
-
-                Ok(project)
+        if names.is_empty() {
+            return Ok(vec![Project::from_config(config, &root)?]);
+        }
+
+        let mut projects: Vec<Project> = Vec::new();
+
+        for name in names {
+            let mut project = Project::from_config(config.target(&name)?, &root)?;
+            project.name = Some(name);
+
+            // tectonic names the pdf after the entry rather than the target,
+            // so targets sharing an entry and a bin would overwrite each
+            // other
+            if let Some(other) = projects.iter().find(|other| other.pdf == project.pdf) {
+                return Err(Error::InvalidConfig(format!(
+                    "targets {} and {} both write {}, give them different bin directories",
+                    other.name().unwrap_or_default(),
+                    project.name().unwrap_or_default(),
+                    project.pdf.display()
+                )));
             }
-            _ => Err(Error::WrongConfigFormat(String::from("expecting object"))),
-        };
+
+            projects.push(project);
+        }
+
+        return Ok(projects);
+    }
+
+    pub fn name(&self) -> Option<&str> {
+        return self.name.as_deref();
+    }
+
+    pub fn jobname(&self) -> &str {
+        return &self.jobname;
     }
 
     pub fn pdf(&self) -> &Path {
@@ -257,12 +295,53 @@ impl Project {
         return &self.aux;
     }
 
+    pub fn log(&self) -> &Path {
+        return &self.log;
+    }
+
     pub fn bin(&self) -> &Path {
         return &self.bin;
     }
 
+    pub fn engine(&self) -> &Engine {
+        return &self.engine;
+    }
+
+    /// The name of the program of the engine
     pub fn latex(&self) -> &OsStr {
-        return &self.latex;
+        return OsStr::new(self.engine.name());
+    }
+
+    pub fn interaction(&self) -> Interaction {
+        return self.interaction;
+    }
+
+    pub fn synctex(&self) -> bool {
+        return self.synctex;
+    }
+
+    pub fn shell_escape(&self) -> Option<ShellEscape> {
+        return self.shell_escape;
+    }
+
+    pub fn file_line_error(&self) -> bool {
+        return self.file_line_error;
+    }
+
+    pub fn engine_output(&self) -> Option<EngineOutput> {
+        return self.engine_output;
+    }
+
+    pub fn timeout(&self) -> Option<Duration> {
+        return self.timeout;
+    }
+
+    pub fn args(&self) -> &Vec<String> {
+        return &self.args;
+    }
+
+    pub fn defines(&self) -> &BTreeMap<String, String> {
+        return &self.defines;
     }
 
     pub fn entry(&self) -> &Path {
@@ -273,6 +352,34 @@ impl Project {
         return &self.files;
     }
 
+    pub fn bibliography(&self) -> Option<Bibliography> {
+        return self.bibliography;
+    }
+
+    pub fn index(&self) -> Index {
+        return self.index;
+    }
+
+    pub fn index_style(&self) -> Option<&Path> {
+        return self.index_style.as_deref();
+    }
+
+    pub fn glossary_style(&self) -> Option<&Path> {
+        return self.glossary_style.as_deref();
+    }
+
+    pub fn rebuild_strategy(&self) -> RebuildStrategy {
+        return self.rebuild_strategy;
+    }
+
+    pub fn max_passes(&self) -> usize {
+        return self.max_passes;
+    }
+
+    pub fn tracked_extensions(&self) -> &Vec<String> {
+        return &self.tracked_extensions;
+    }
+
     /// Use a root path
     ///
     /// # Arguments
@@ -294,56 +401,66 @@ impl Project {
 
         self.files = files;
 
+        // style files
+        self.index_style = self
+            .index_style
+            .as_ref()
+            .map(|style| with_prepend(style, root_path));
+        self.glossary_style = self
+            .glossary_style
+            .as_ref()
+            .map(|style| with_prepend(style, root_path));
+
         // pdf
         self.pdf = with_prepend(&self.pdf, root_path);
 
         // aux
         self.aux = with_prepend(&self.aux, root_path);
+
+        // log
+        self.log = with_prepend(&self.log, root_path);
     }
 }
 
-impl Into<JsonValue> for Project {
-    fn into(self) -> JsonValue {
-        let mut object = Object::new();
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::write;
 
-        match self.latex.to_str() {
-            Some(s) => {
-                object.insert("latex", JsonValue::String(String::from(s)));
-            }
-            None => {
-                object.insert("latex", JsonValue::String(String::from("pdflatex")));
-            }
-        }
+    fn load(config: &str) -> Result<Vec<Project>, Error> {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("latexproject.json");
+        write(&path, config).unwrap();
 
-        match self.bin.to_str() {
-            Some(s) => {
-                object.insert("bin", JsonValue::String(String::from(s)));
-            }
-            None => {
-                object.insert("bin", JsonValue::String(String::from("bin")));
-            }
-        }
+        return Project::load_targets(&path, None, None);
+    }
 
-        match self.entry.to_str() {
-            Some(s) => {
-                object.insert("entry", JsonValue::String(String::from(s)));
-            }
-            None => {
-                object.insert("entry", JsonValue::String(String::from("index.tex")));
-            }
-        }
+    #[test]
+    fn targets_are_named_after_their_output() {
+        let projects = load(
+            r#"{ "targets": { "paper": { "entry": "paper.tex" },
+                 "slides": { "entry": "paper.tex", "output": "talk" } } }"#,
+        )
+        .unwrap();
 
-        let mut includes: Vec<JsonValue> = Vec::new();
+        assert_eq!(projects[0].jobname(), "paper");
+        assert_eq!(projects[1].jobname(), "talk");
+    }
 
-        for file in self.files {
-            match file.to_str() {
-                Some(s) => includes.push(JsonValue::String(String::from(s))),
-                _ => {}
-            }
-        }
+    #[test]
+    fn tectonic_targets_sharing_an_entry_are_rejected() {
+        let result = load(
+            r#"{ "latex": "tectonic", "targets": { "paper": { "entry": "paper.tex" },
+                 "slides": { "entry": "paper.tex", "output": "talk" } } }"#,
+        );
+
+        assert!(matches!(result, Err(Error::InvalidConfig(_))));
+    }
 
-        object.insert("includes", JsonValue::Array(includes));
+    #[test]
+    fn tectonic_defines_are_rejected() {
+        let result = load(r#"{ "latex": "tectonic", "defines": { "draft": "1" } }"#);
 
-        return JsonValue::Object(object);
+        assert!(matches!(result, Err(Error::InvalidConfig(_))));
     }
 }
@@ -0,0 +1,209 @@
+use super::{absolute, Project};
+use std::fs::read;
+use std::path::{Component, Path, PathBuf};
+use std::process::Command;
+use std::sync::OnceLock;
+
+/// The kpathsea variables naming the directories of the TeX distribution,
+/// and of the files it generates such as formats and font caches
+const DISTRIBUTION_VARIABLES: [&str; 8] = [
+    "TEXMFROOT",
+    "TEXMFMAIN",
+    "TEXMFDIST",
+    "TEXMFLOCAL",
+    "TEXMFSYSVAR",
+    "TEXMFSYSCONFIG",
+    "TEXMFVAR",
+    "TEXMFCONFIG",
+];
+
+/// The directories of the TeX distribution, asked to kpsewhich once. No
+/// directory is returned if kpsewhich cannot be run, for example with
+/// tectonic, which does not write fls files
+fn distribution_dirs() -> &'static [PathBuf] {
+    static DIRS: OnceLock<Vec<PathBuf>> = OnceLock::new();
+
+    return DIRS.get_or_init(|| {
+        let mut dirs: Vec<PathBuf> = Vec::new();
+
+        for variable in &DISTRIBUTION_VARIABLES {
+            let output = match Command::new("kpsewhich")
+                .arg(format!("-var-value={}", variable))
+                .output()
+            {
+                Ok(output) => output,
+                Err(_) => break,
+            };
+
+            let value = String::from_utf8_lossy(&output.stdout);
+            let value = value.trim().trim_start_matches("!!");
+
+            // unset variables are empty, and lists of directories are not
+            // expanded by -var-value
+            if !output.status.success() || value.is_empty() || value.contains('{') {
+                continue;
+            }
+
+            dirs.push(PathBuf::from(value));
+        }
+
+        return dirs;
+    });
+}
+
+/// Resolve the `..` components of an absolute path, so that `../figures`
+/// read from the project is not taken to be inside of it
+fn without_parent_dirs(path: &Path) -> PathBuf {
+    let mut resolved = PathBuf::new();
+
+    for component in path.components() {
+        match component {
+            Component::ParentDir => {
+                resolved.pop();
+            }
+            Component::CurDir => {}
+            _ => resolved.push(component),
+        }
+    }
+
+    return resolved;
+}
+
+impl Project {
+    /// The fls file written by latex when run with `-recorder`
+    pub fn fls(&self) -> PathBuf {
+        return self.aux().with_extension("fls");
+    }
+
+    /// The files read by the last latex pass, as recorded in the fls file.
+    ///
+    /// The files of the TeX distribution are excluded, and so are the files
+    /// inside the bin directory as they are generated by the build. The
+    /// files inside the directory of the entry are relative to the current
+    /// directory in the same way as the other paths of the project, the
+    /// files outside of it, such as figures shared between projects, are
+    /// absolute
+    pub fn recorded_files(&self) -> Vec<PathBuf> {
+        let mut files: Vec<PathBuf> = Vec::new();
+
+        let fls = match read(self.fls()) {
+            Ok(fls) => String::from_utf8_lossy(&fls).into_owned(),
+            Err(_) => return files,
+        };
+
+        let root = match self.entry().parent() {
+            Some(root) => PathBuf::from(root),
+            None => PathBuf::new(),
+        };
+
+        let absolute_root = absolute(&root);
+        let absolute_bin = absolute(self.bin());
+
+        // relative paths in the fls file are relative to the directory latex
+        // has been run in
+        let mut pwd = absolute(Path::new(""));
+
+        for line in fls.lines() {
+            if let Some(dir) = line.strip_prefix("PWD ") {
+                pwd = PathBuf::from(dir);
+                continue;
+            }
+
+            let input = match line.strip_prefix("INPUT ") {
+                Some(input) => without_parent_dirs(&pwd.join(input)),
+                None => continue,
+            };
+
+            if input.starts_with(&absolute_bin)
+                || distribution_dirs().iter().any(|dir| input.starts_with(dir))
+            {
+                continue;
+            }
+
+            let file = match input.strip_prefix(&absolute_root) {
+                Ok(relative) => root.join(relative),
+                Err(_) => input,
+            };
+
+            if file.is_file() && !files.contains(&file) {
+                files.push(file);
+            }
+        }
+
+        return files;
+    }
+
+    /// The files the build depends on: the resolved `includes` and entry, the
+    /// files recorded by the last latex pass and the bib files
+    pub fn dependencies(&self) -> Vec<PathBuf> {
+        let mut files = self.files().clone();
+        let mut absolute_files: Vec<PathBuf> = files.iter().map(|file| absolute(file)).collect();
+
+        for file in self.recorded_files().into_iter().chain(self.bib_files()) {
+            let absolute_file = absolute(&file);
+
+            if !absolute_files.contains(&absolute_file) {
+                absolute_files.push(absolute_file);
+                files.push(file);
+            }
+        }
+
+        return files;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::{create_dir, write};
+
+    #[test]
+    fn parent_dirs_are_resolved() {
+        assert_eq!(
+            without_parent_dirs(Path::new("/home/paper/./../figures/plot.pdf")),
+            PathBuf::from("/home/figures/plot.pdf")
+        );
+    }
+
+    #[test]
+    fn recorded_files_are_the_sources_read() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("paper");
+        let shared = dir.path().join("shared");
+        create_dir(&root).unwrap();
+        create_dir(&shared).unwrap();
+        create_dir(root.join("bin")).unwrap();
+
+        let config = root.join("latexproject.json");
+        write(&config, "{}").unwrap();
+        write(root.join("index.tex"), "\\input{intro}").unwrap();
+        write(root.join("intro.tex"), "Introduction").unwrap();
+        write(shared.join("macros.tex"), "\\newcommand{\\R}{\\mathbb{R}}").unwrap();
+        write(root.join("bin").join("index.aux"), "\\relax").unwrap();
+
+        let mut project = Project::load(&config).unwrap();
+        project.use_root_path(&root);
+
+        let fls = [
+            format!("PWD {}", root.display()),
+            String::from("INPUT index.tex"),
+            String::from("OUTPUT bin/index.log"),
+            String::from("INPUT ./intro.tex"),
+            String::from("INPUT intro.tex"),
+            String::from("INPUT ../shared/macros.tex"),
+            String::from("INPUT bin/index.aux"),
+            String::from("OUTPUT bin/index.aux"),
+            String::from("INPUT missing.tex"),
+        ];
+        write(project.fls(), fls.join("\n")).unwrap();
+
+        assert_eq!(
+            project.recorded_files(),
+            vec![
+                root.join("index.tex"),
+                root.join("intro.tex"),
+                shared.join("macros.tex"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,311 @@
+use super::{absolute, relative_to_root, Diagnostic, DiagnosticKind, Error, Project};
+use serde_json::{json, Map, Value};
+use std::ffi::OsStr;
+use std::fs::{remove_file, write};
+use std::io::ErrorKind;
+use std::path::{Component, Path, PathBuf, Prefix};
+
+/// The version of SARIF the reports follow
+const SARIF_VERSION: &str = "2.1.0";
+
+/// The schema of the reports
+const SARIF_SCHEMA: &str = "https://json.schemastore.org/sarif-2.1.0.json";
+
+/// The base the locations in the project are relative to, resolved by the
+/// `originalUriBaseIds` of the report
+const SOURCE_ROOT: &str = "%SRCROOT%";
+
+/// The rules of the report, one per kind of diagnostic, with a description
+const RULES: [(&str, &str); 8] = [
+    ("error", "TeX or LaTeX error"),
+    ("overfull-box", "Content does not fit in its box"),
+    ("underfull-box", "Box is not filled by its content"),
+    ("undefined-reference", "Reference to an undefined label"),
+    ("undefined-citation", "Citation of an undefined key"),
+    (
+        "font-substitution",
+        "Font shape not available and substituted",
+    ),
+    ("package-warning", "Warning from a package or class"),
+    ("latex-warning", "Warning from LaTeX"),
+];
+
+/// Percent-encode a component of a path, keeping the characters that need
+/// no encoding
+fn encode_segment(segment: &OsStr) -> String {
+    let mut encoded = String::new();
+
+    for byte in segment.to_string_lossy().bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                encoded.push(char::from(byte));
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+
+    return encoded;
+}
+
+/// Encode a path as the path of a URI, with its components separated by `/`
+/// on every platform and a drive letter kept as `C:`
+fn to_uri_path(path: &Path) -> String {
+    let mut segments: Vec<String> = Vec::new();
+
+    for component in path.components() {
+        match component {
+            Component::Prefix(prefix) => match prefix.kind() {
+                Prefix::Disk(letter) | Prefix::VerbatimDisk(letter) => {
+                    segments.push(format!("{}:", char::from(letter)));
+                }
+                _ => segments.push(encode_segment(prefix.as_os_str())),
+            },
+            // the root of a path with a drive letter is the `/` after it
+            Component::RootDir => {
+                if segments.is_empty() {
+                    segments.push(String::new());
+                }
+            }
+            Component::CurDir => {}
+            Component::ParentDir => segments.push(String::from("..")),
+            Component::Normal(name) => segments.push(encode_segment(name)),
+        }
+    }
+
+    return segments.join("/");
+}
+
+/// The `file` URI of an absolute path, `file:///home/a.tex` or
+/// `file:///C:/a.tex`
+fn file_uri(path: &Path) -> String {
+    let uri_path = to_uri_path(path);
+
+    if uri_path.starts_with('/') {
+        return format!("file://{}", uri_path);
+    }
+
+    return format!("file:///{}", uri_path);
+}
+
+/// The SARIF location of a diagnostic, `None` if the log does not say which
+/// file it comes from
+///
+/// # Arguments
+///
+/// - `diagnostic`: the diagnostic
+/// - `root`: the project root, the files in it are relative to `%SRCROOT%`
+fn location(diagnostic: &Diagnostic, root: &Path) -> Option<Value> {
+    let file = diagnostic.file.as_ref()?;
+    let relative = relative_to_root(file, root);
+
+    // files outside of the project, such as the classes of the tex
+    // distribution, are given with their absolute path
+    let artifact_location = if relative.is_relative() {
+        json!({
+            "uri": to_uri_path(&relative),
+            "uriBaseId": SOURCE_ROOT,
+        })
+    } else {
+        json!({
+            "uri": file_uri(&relative),
+        })
+    };
+
+    let mut physical_location = Map::new();
+    physical_location.insert(String::from("artifactLocation"), artifact_location);
+
+    if let Some(line) = diagnostic.line {
+        physical_location.insert(String::from("region"), json!({ "startLine": line }));
+    }
+
+    return Some(json!({ "physicalLocation": physical_location }));
+}
+
+/// The SARIF result of a diagnostic
+fn result(diagnostic: &Diagnostic, root: &Path) -> Value {
+    let rule_id = diagnostic.kind.name();
+    let rule_index = RULES.iter().position(|(id, _)| *id == rule_id);
+
+    let level = match diagnostic.kind {
+        DiagnosticKind::Error => "error",
+        DiagnosticKind::UnderfullBox => "note",
+        _ => "warning",
+    };
+
+    let mut result = Map::new();
+    result.insert(String::from("ruleId"), json!(rule_id));
+    result.insert(String::from("ruleIndex"), json!(rule_index));
+    result.insert(String::from("level"), json!(level));
+    result.insert(
+        String::from("message"),
+        json!({ "text": diagnostic.message }),
+    );
+
+    if let Some(location) = location(diagnostic, root) {
+        result.insert(String::from("locations"), json!([location]));
+    }
+
+    return Value::Object(result);
+}
+
+/// Make a SARIF report of diagnostics
+///
+/// # Arguments
+///
+/// - `diagnostics`: the diagnostics
+/// - `root`: the project root, which the locations are relative to
+pub fn sarif_report(diagnostics: &[Diagnostic], root: &Path) -> Value {
+    let rules: Vec<Value> = RULES
+        .iter()
+        .map(|(id, description)| {
+            json!({
+                "id": id,
+                "shortDescription": { "text": description },
+            })
+        })
+        .collect();
+
+    let results: Vec<Value> = diagnostics
+        .iter()
+        .map(|diagnostic| result(diagnostic, root))
+        .collect();
+
+    // the base URI of a directory ends with a `/`
+    let root_uri = format!("{}/", file_uri(&absolute(root)));
+
+    return json!({
+        "$schema": SARIF_SCHEMA,
+        "version": SARIF_VERSION,
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": "latexbuild",
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "rules": rules,
+                },
+            },
+            "originalUriBaseIds": {
+                SOURCE_ROOT: { "uri": root_uri },
+            },
+            "results": results,
+        }],
+    });
+}
+
+impl Project {
+    /// The SARIF report of the last build, one per job so that the targets
+    /// sharing a bin directory do not overwrite each other's report
+    pub fn sarif_file(&self) -> PathBuf {
+        return self.bin().join(format!("{}.sarif", self.jobname()));
+    }
+
+    /// Remove the SARIF report of an earlier build, so that a build that stops
+    /// before its first pass does not leave it behind
+    pub fn remove_sarif(&self) -> Result<(), Error> {
+        let path = self.sarif_file();
+
+        return match remove_file(&path) {
+            Ok(_) => Ok(()),
+            Err(error) if error.kind() == ErrorKind::NotFound => Ok(()),
+            Err(error) => Err(Error::IO("remove", path, error)),
+        };
+    }
+
+    /// Write the SARIF report of the diagnostics of the last pass
+    ///
+    /// # Arguments
+    ///
+    /// - `diagnostics`: the diagnostics
+    /// - `root`: the project root, which the locations are relative to
+    pub fn save_sarif(&self, diagnostics: &[Diagnostic], root: &Path) -> Result<(), Error> {
+        let path = self.sarif_file();
+
+        let content = match serde_json::to_string_pretty(&sarif_report(diagnostics, root)) {
+            Ok(content) => content,
+            Err(error) => return Err(Error::Serialization(path, error)),
+        };
+
+        return match write(&path, content.as_bytes()) {
+            Ok(_) => Ok(()),
+            Err(error) => Err(Error::IO("write", path, error)),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn relative_paths_are_encoded_by_component() {
+        assert_eq!(
+            to_uri_path(Path::new("chapters/my thesis#1.tex")),
+            "chapters/my%20thesis%231.tex"
+        );
+        assert_eq!(to_uri_path(Path::new("./index.tex")), "index.tex");
+        assert_eq!(
+            to_uri_path(Path::new("../shared/é.tex")),
+            "../shared/%C3%A9.tex"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn absolute_paths_are_file_uris() {
+        assert_eq!(
+            file_uri(Path::new("/usr/share/texlive/article.cls")),
+            "file:///usr/share/texlive/article.cls"
+        );
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn absolute_paths_are_file_uris() {
+        assert_eq!(
+            file_uri(Path::new("C:\\texlive\\article.cls")),
+            "file:///C:/texlive/article.cls"
+        );
+    }
+
+    #[test]
+    fn report_locations() {
+        let root = absolute(Path::new("project"));
+        let diagnostics = vec![
+            Diagnostic {
+                kind: DiagnosticKind::UndefinedCitation(String::from("knuth")),
+                file: Some(root.join("chapters").join("intro.tex")),
+                line: Some(3),
+                message: String::from("Citation `knuth' undefined"),
+            },
+            Diagnostic {
+                kind: DiagnosticKind::Error,
+                file: None,
+                line: None,
+                message: String::from("Emergency stop"),
+            },
+        ];
+
+        let report = sarif_report(&diagnostics, &root);
+        let run = &report["runs"][0];
+
+        assert_eq!(
+            run["originalUriBaseIds"][SOURCE_ROOT]["uri"],
+            json!(format!("{}/", file_uri(&root)))
+        );
+
+        let citation = &run["results"][0];
+        assert_eq!(citation["ruleId"], json!("undefined-citation"));
+        assert_eq!(citation["level"], json!("warning"));
+        assert_eq!(
+            citation["locations"][0]["physicalLocation"],
+            json!({
+                "artifactLocation": { "uri": "chapters/intro.tex", "uriBaseId": SOURCE_ROOT },
+                "region": { "startLine": 3 },
+            })
+        );
+
+        let error = &run["results"][1];
+        assert_eq!(error["level"], json!("error"));
+        assert!(error.get("locations").is_none());
+    }
+}
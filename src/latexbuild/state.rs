@@ -0,0 +1,205 @@
+use super::{absolute, Error, Project};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs::{read, remove_file, write};
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+/// How `NeedsBuildChecker` determines if the sources have changed since the
+/// last build
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RebuildStrategy {
+    /// A source has changed if it is newer than the pdf
+    Mtime,
+    /// A source has changed if its hash differs from the hash recorded in the
+    /// build state at the last successful build
+    Hash,
+    /// A source has changed if it is newer than the pdf and its hash differs
+    /// from the hash recorded in the build state
+    MtimeThenHash,
+}
+
+impl RebuildStrategy {
+    pub fn name(&self) -> &'static str {
+        return match self {
+            RebuildStrategy::Mtime => "mtime",
+            RebuildStrategy::Hash => "hash",
+            RebuildStrategy::MtimeThenHash => "mtime-then-hash",
+        };
+    }
+}
+
+/// Hash content with SHA-256, which unlike the hasher of the standard library
+/// is stable across builds of latexbuild
+pub fn hash_content(content: &[u8]) -> String {
+    return format!("{:x}", Sha256::digest(content));
+}
+
+/// Hash the content of a file, see `hash_content`
+pub fn hash_file(path: &Path) -> Result<String, Error> {
+    return match read(path) {
+        Ok(content) => Ok(hash_content(&content)),
+        Err(_) => Err(Error::PathNotFound(PathBuf::from(path))),
+    };
+}
+
+/// The hashes of the dependencies at the last successful build, persisted in
+/// the bin directory
+#[derive(Default, Serialize, Deserialize)]
+pub struct BuildState {
+    #[serde(rename = "files")]
+    hashes: BTreeMap<PathBuf, String>,
+}
+
+impl BuildState {
+    /// Load the build state, an empty state is returned if the state file
+    /// does not exist or cannot be parsed
+    ///
+    /// # Arguments
+    ///
+    /// - `path`: the path to the state file
+    pub fn load(path: &Path) -> BuildState {
+        return match read(path) {
+            Ok(content) => serde_json::from_slice(&content).unwrap_or_default(),
+            Err(_) => BuildState::default(),
+        };
+    }
+
+    /// Create a build state with the current hashes of files
+    ///
+    /// # Arguments
+    ///
+    /// - `files`: the files to hash
+    pub fn from_files(files: &[PathBuf]) -> Result<BuildState, Error> {
+        let mut hashes: BTreeMap<PathBuf, String> = BTreeMap::new();
+
+        for file in files {
+            hashes.insert(absolute(file), hash_file(file)?);
+        }
+
+        return Ok(BuildState { hashes });
+    }
+
+    /// Determine if a file has the same hash as recorded in the state, files
+    /// not in the state are considered changed
+    pub fn is_unchanged(&self, file: &Path) -> Result<bool, Error> {
+        return match self.hashes.get(&absolute(file)) {
+            Some(hash) => Ok(*hash == hash_file(file)?),
+            None => Ok(false),
+        };
+    }
+
+    /// Save the build state
+    ///
+    /// # Arguments
+    ///
+    /// - `path`: the path to the state file
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        let content = match serde_json::to_string_pretty(self) {
+            Ok(content) => content,
+            Err(error) => return Err(Error::Serialization(PathBuf::from(path), error)),
+        };
+
+        return match write(path, content.as_bytes()) {
+            Ok(_) => Ok(()),
+            Err(error) => Err(Error::IO("write", PathBuf::from(path), error)),
+        };
+    }
+}
+
+impl Project {
+    /// The file the build state is persisted in, one per job so that the
+    /// targets sharing a bin directory do not overwrite each other's state
+    pub fn state_file(&self) -> PathBuf {
+        return self
+            .bin()
+            .join(format!(".{}.latexbuild-state.json", self.jobname()));
+    }
+
+    /// The file marking that the last build of the job has failed, so that
+    /// the pdf a failed build leaves behind is not taken for up to date
+    pub fn failure_file(&self) -> PathBuf {
+        return self
+            .bin()
+            .join(format!(".{}.latexbuild-failed", self.jobname()));
+    }
+
+    /// Record whether the last build has failed
+    ///
+    /// # Arguments
+    ///
+    /// - `failed`: `true` if the build has failed
+    pub fn save_failure(&self, failed: bool) -> Result<(), Error> {
+        let path = self.failure_file();
+
+        let result = if failed {
+            write(&path, b"")
+        } else {
+            match remove_file(&path) {
+                Err(error) if error.kind() == ErrorKind::NotFound => Ok(()),
+                result => result,
+            }
+        };
+
+        return match result {
+            Ok(_) => Ok(()),
+            Err(error) => Err(Error::IO("write", path, error)),
+        };
+    }
+
+    /// Record the hashes of the dependencies after a successful build
+    pub fn save_state(&self) -> Result<(), Error> {
+        return BuildState::from_files(&self.dependencies())?.save(&self.state_file());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::write;
+
+    #[test]
+    fn hash_content_is_sha256() {
+        assert_eq!(
+            hash_content(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(
+            hash_content(b""),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+    }
+
+    #[test]
+    fn state_detects_changed_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let tex = dir.path().join("index.tex");
+        let bib = dir.path().join("references.bib");
+        let state_file = dir.path().join("state.json");
+        write(&tex, "\\documentclass{article}").unwrap();
+        write(&bib, "@book{knuth}").unwrap();
+
+        BuildState::from_files(&[tex.clone(), bib.clone()])
+            .unwrap()
+            .save(&state_file)
+            .unwrap();
+        write(&bib, "@book{lamport}").unwrap();
+
+        let state = BuildState::load(&state_file);
+        assert!(state.is_unchanged(&tex).unwrap());
+        assert!(!state.is_unchanged(&bib).unwrap());
+        assert!(!state.is_unchanged(&dir.path().join("other.tex")).unwrap());
+    }
+
+    #[test]
+    fn missing_or_corrupt_state_is_empty() {
+        let dir = tempfile::tempdir().unwrap();
+        let state_file = dir.path().join("state.json");
+        assert!(BuildState::load(&state_file).hashes.is_empty());
+
+        write(&state_file, "not json").unwrap();
+        assert!(BuildState::load(&state_file).hashes.is_empty());
+    }
+}
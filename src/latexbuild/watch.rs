@@ -0,0 +1,171 @@
+use super::{Error, LatexBuild, Logger, Project};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::thread::sleep;
+use std::time::{Duration, Instant, SystemTime};
+
+/// How often the watched files are polled
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// How long the watched files must stay unchanged before a build starts
+const DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// How often the config is reloaded and the includes resolved again, so that
+/// files newly created in included directories are watched
+const RELOAD_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Modification times of the watched files, `None` if a file is missing
+type Snapshot = HashMap<PathBuf, Option<SystemTime>>;
+
+/// Take a snapshot of the dependencies of the projects and their config.
+/// Files inside the bin directory are never watched, so that the outputs of a
+/// build do not trigger another build
+///
+/// # Arguments
+///
+/// - `projects`: the projects of the targets, if the config could be loaded
+/// - `config_path`: the path to the config
+fn snapshot(projects: Option<&[Project]>, config_path: &Path) -> Snapshot {
+    let mut snapshot = Snapshot::new();
+
+    snapshot.insert(PathBuf::from(config_path), modified(config_path));
+
+    for project in projects.unwrap_or_default() {
+        for file in project.dependencies() {
+            if file.starts_with(project.bin()) {
+                continue;
+            }
+
+            let file_modified = modified(&file);
+            snapshot.insert(file, file_modified);
+        }
+    }
+
+    return snapshot;
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    return path.metadata().and_then(|meta| meta.modified()).ok();
+}
+
+impl<'a, L> LatexBuild<'a, L>
+where
+    L: Logger,
+{
+    /// Run the build pipeline, then keep running the pipeline whenever the
+    /// config or any of the dependencies of the targets changes. The config is
+    /// reloaded and the includes are resolved again after every build and
+    /// every `RELOAD_INTERVAL`, so files newly created in included
+    /// directories are picked up.
+    ///
+    /// This method does not return; errors from loading the config or from a
+    /// build are logged and watching continues
+    pub fn watch(&mut self) -> Result<(), Error> {
+        if let Err(error) = self.build() {
+            self.logger.error(format!("{}", error));
+        }
+
+        let mut projects = self.load_projects().ok();
+        let mut loaded = Instant::now();
+        let mut last = snapshot(projects.as_deref(), &self.config_path);
+
+        self.logger.message("watching for changes");
+
+        loop {
+            sleep(POLL_INTERVAL);
+
+            if loaded.elapsed() >= RELOAD_INTERVAL {
+                projects = self.load_projects().ok();
+                loaded = Instant::now();
+            }
+
+            let mut current = snapshot(projects.as_deref(), &self.config_path);
+
+            if current == last {
+                continue;
+            }
+
+            // Wait for the files to settle, so that a burst of writes (an
+            // editor saving several files, a git checkout) only leads to one
+            // build
+            loop {
+                sleep(DEBOUNCE);
+
+                let settled = snapshot(projects.as_deref(), &self.config_path);
+
+                if settled == current {
+                    break;
+                }
+
+                current = settled;
+            }
+
+            self.logger.message("change detected");
+
+            if let Err(error) = self.build() {
+                self.logger.error(format!("{}", error));
+            }
+
+            // The build records the files it has read, so the dependencies
+            // are only known once it has finished
+            projects = self.load_projects().ok();
+            loaded = Instant::now();
+            last = snapshot(projects.as_deref(), &self.config_path);
+
+            self.logger.message("watching for changes");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::{create_dir, write};
+
+    #[test]
+    fn snapshot_has_the_config_and_the_dependencies() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = dir.path().join("latexproject.json");
+        write(&config, "{}").unwrap();
+        write(dir.path().join("index.tex"), "\\input{intro}").unwrap();
+        create_dir(dir.path().join("bin")).unwrap();
+
+        let mut project = Project::load(&config).unwrap();
+        project.use_root_path(dir.path());
+        let projects = vec![project];
+
+        let before = snapshot(Some(&projects), &config);
+        assert!(before[&config].is_some());
+        assert!(before[&dir.path().join("index.tex")].is_some());
+
+        // the files read by a build are only known once it has finished
+        let intro = dir.path().join("intro.tex");
+        write(&intro, "Introduction").unwrap();
+        write(
+            projects[0].fls(),
+            format!(
+                "PWD {}\nINPUT intro.tex\nINPUT bin/index.aux\n",
+                dir.path().display()
+            ),
+        )
+        .unwrap();
+
+        let after = snapshot(Some(&projects), &config);
+        assert_ne!(before, after);
+        assert!(after[&intro].is_some());
+        assert_eq!(after.len(), 3);
+    }
+
+    #[test]
+    fn snapshot_without_projects_has_the_config() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = dir.path().join("latexproject.json");
+
+        let missing = snapshot(None, &config);
+        assert_eq!(missing.len(), 1);
+        assert_eq!(missing[&config], None);
+
+        write(&config, "{").unwrap();
+        assert_ne!(snapshot(None, &config), missing);
+    }
+}